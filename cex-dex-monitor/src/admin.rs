@@ -0,0 +1,518 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use cex_dex_monitor::AlertKind;
+use hyper::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tracing::info;
+
+use crate::{
+    cexdexclient::dto::StateData,
+    latency::{self, StateLatency},
+    routing::{SilenceTarget, Silences},
+    scheduler,
+};
+
+// states kept for GET /states
+const RECENT_STATES: usize = 100;
+
+// Reports is what the monitors publish for the admin api
+pub struct Reports {
+    balances: Mutex<BTreeMap<String, BalanceReport>>,
+    diffs: Mutex<BTreeMap<String, DiffReport>>,
+    states: Mutex<VecDeque<StateReport>>,
+    // one per env with a balance monitor
+    snapshot_triggers: Mutex<HashMap<String, Arc<Notify>>>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BalanceReport {
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub balances: BTreeMap<String, Decimal>,
+    pub usd_value: Option<Decimal>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct DiffReport {
+    pub kind: AlertKind,
+    pub from: chrono::DateTime<chrono::Utc>,
+    pub to: chrono::DateTime<chrono::Utc>,
+    pub changes: BTreeMap<String, Decimal>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct StateReport {
+    pub env: String,
+    pub state_id: String,
+    pub token: String,
+    pub side: String,
+    pub notified_at: chrono::DateTime<chrono::Utc>,
+    pub latency: StateLatency,
+}
+
+static REPORTS: OnceLock<Reports> = OnceLock::new();
+
+pub fn get() -> &'static Reports {
+    REPORTS.get_or_init(|| Reports {
+        balances: Mutex::new(BTreeMap::new()),
+        diffs: Mutex::new(BTreeMap::new()),
+        states: Mutex::new(VecDeque::new()),
+        snapshot_triggers: Mutex::new(HashMap::new()),
+    })
+}
+
+impl Reports {
+    pub fn set_balances(
+        &self,
+        env: &str,
+        balances: &HashMap<String, Decimal>,
+        usd_value: Option<Decimal>,
+    ) {
+        self.balances.lock().unwrap().insert(
+            env.to_string(),
+            BalanceReport {
+                updated_at: scheduler::now(),
+                balances: balances.iter().map(|(k, v)| (k.clone(), *v)).collect(),
+                usd_value,
+            },
+        );
+    }
+
+    pub fn set_last_diff(
+        &self,
+        env: &str,
+        kind: AlertKind,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+        changes: &[(String, Decimal)],
+    ) {
+        self.diffs.lock().unwrap().insert(
+            env.to_string(),
+            DiffReport {
+                kind,
+                from,
+                to,
+                changes: changes.iter().cloned().collect(),
+            },
+        );
+    }
+
+    pub fn add_state(&self, env: &str, state: &StateData, latency: StateLatency) {
+        let mut states = self.states.lock().unwrap();
+        if states.len() >= RECENT_STATES {
+            states.pop_front();
+        }
+        states.push_back(StateReport {
+            env: env.to_string(),
+            state_id: state.state_id.clone(),
+            token: state.token.clone(),
+            side: state.side.clone(),
+            notified_at: scheduler::now(),
+            latency,
+        });
+    }
+
+    // the balance monitor of env waits on it between two checks
+    pub fn snapshot_trigger(&self, env: &str) -> SnapshotTrigger {
+        let notify = self
+            .snapshot_triggers
+            .lock()
+            .unwrap()
+            .entry(env.to_string())
+            .or_default()
+            .clone();
+        SnapshotTrigger {
+            env: env.to_string(),
+            notify,
+        }
+    }
+
+    // unregistered with the last monitor of env
+    fn release_snapshot_trigger(&self, env: &str, notify: &Arc<Notify>) {
+        let mut triggers = self.snapshot_triggers.lock().unwrap();
+        // the map and the trigger being released
+        let last = triggers
+            .get(env)
+            .is_some_and(|n| Arc::ptr_eq(n, notify) && Arc::strong_count(n) <= 2);
+        if last {
+            triggers.remove(env);
+        }
+    }
+
+    // false when env has no balance monitor
+    fn trigger_snapshot(&self, env: &str) -> bool {
+        match self.snapshot_triggers.lock().unwrap().get(env) {
+            None => false,
+            Some(n) => {
+                n.notify_one();
+                true
+            }
+        }
+    }
+}
+
+// registered while the balance monitor of env runs, a stopped monitor is not
+// triggered anymore
+pub struct SnapshotTrigger {
+    env: String,
+    notify: Arc<Notify>,
+}
+
+impl SnapshotTrigger {
+    pub fn notify(&self) -> Arc<Notify> {
+        self.notify.clone()
+    }
+}
+
+impl Drop for SnapshotTrigger {
+    fn drop(&mut self) {
+        get().release_snapshot_trigger(&self.env, &self.notify);
+    }
+}
+
+struct Context {
+    token: String,
+    silences: Arc<Silences>,
+}
+
+// serve the admin api until the process exits
+pub async fn serve(addr: SocketAddr, token: String, silences: Arc<Silences>) -> anyhow::Result<()> {
+    if token.is_empty() {
+        return Err(anyhow::format_err!("empty admin token"));
+    }
+    let ctx = Arc::new(Context { token, silences });
+    let make_svc = make_service_fn(move |_| {
+        let ctx = ctx.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(ctx.clone(), req))) }
+    });
+
+    info!(%addr, "admin server listening");
+    hyper::Server::try_bind(&addr)?.serve(make_svc).await?;
+
+    Ok(())
+}
+
+async fn handle(ctx: Arc<Context>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let authorized = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .is_some_and(|t| constant_time_eq(t.as_bytes(), ctx.token.as_bytes()));
+    if !authorized {
+        return Ok(text(StatusCode::UNAUTHORIZED, "unauthorized"));
+    }
+
+    let query = parse_query(req.uri().query().unwrap_or_default());
+    let env = query.get("env");
+    let r = get();
+    let resp = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/balances") => json(&filter_env(&r.balances.lock().unwrap(), env)),
+        (&Method::GET, "/diff") => json(&filter_env(&r.diffs.lock().unwrap(), env)),
+        (&Method::GET, "/states") => {
+            let limit = query
+                .get("limit")
+                .and_then(|l| l.parse::<usize>().ok())
+                .unwrap_or(RECENT_STATES);
+            let states = r
+                .states
+                .lock()
+                .unwrap()
+                .iter()
+                .rev()
+                .filter(|s| env.is_none_or(|e| s.env.eq(e)))
+                .take(limit)
+                .cloned()
+                .collect::<Vec<StateReport>>();
+            json(&states)
+        }
+        (&Method::GET, "/latency") => json(&filter_env(&latency::get().report(), env)),
+        (&Method::POST, "/snapshot") => match env {
+            None => text(StatusCode::BAD_REQUEST, "missing env"),
+            Some(e) if r.trigger_snapshot(e) => text(StatusCode::ACCEPTED, "snapshot requested"),
+            Some(_) => text(StatusCode::NOT_FOUND, "no balance monitor for env"),
+        },
+        (&Method::GET, "/silences") => json(&silences_json(&ctx.silences)),
+        (&Method::POST, "/silence") => {
+            // None on overflow
+            let until = query
+                .get("minutes")
+                .and_then(|m| m.parse::<i64>().ok())
+                .filter(|m| *m > 0)
+                .and_then(chrono::TimeDelta::try_minutes)
+                .and_then(|d| scheduler::now().checked_add_signed(d));
+            match (silence_target(&query), until) {
+                (Some(target), Some(until)) => {
+                    info!(?target, %until, "silence");
+                    ctx.silences.silence(target, until);
+                    json(&silences_json(&ctx.silences))
+                }
+                _ => text(
+                    StatusCode::BAD_REQUEST,
+                    "expected env or channel, and minutes > 0 within range",
+                ),
+            }
+        }
+        (&Method::DELETE, "/silence") => match silence_target(&query) {
+            None => text(StatusCode::BAD_REQUEST, "expected env or channel"),
+            Some(target) if ctx.silences.lift(&target) => json(&silences_json(&ctx.silences)),
+            Some(_) => text(StatusCode::NOT_FOUND, "not silenced"),
+        },
+        _ => text(StatusCode::NOT_FOUND, "not found"),
+    };
+
+    Ok(resp)
+}
+
+// percent decoded, + is a space
+fn parse_query(query: &str) -> HashMap<String, String> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect()
+}
+
+// the time does not depend on where the tokens differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn filter_env<T: Clone>(m: &BTreeMap<String, T>, env: Option<&String>) -> BTreeMap<String, T> {
+    m.iter()
+        .filter(|(k, _)| env.is_none_or(|e| e.eq(*k)))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+fn silence_target(query: &HashMap<String, String>) -> Option<SilenceTarget> {
+    match (query.get("env"), query.get("channel")) {
+        (Some(e), None) => Some(SilenceTarget::Env(e.clone())),
+        (None, Some(c)) => Some(SilenceTarget::Channel(c.clone())),
+        _ => None,
+    }
+}
+
+#[derive(Serialize)]
+struct SilenceJson {
+    target: SilenceTarget,
+    until: chrono::DateTime<chrono::Utc>,
+}
+
+fn silences_json(silences: &Silences) -> Vec<SilenceJson> {
+    let mut active = silences
+        .active(scheduler::now())
+        .into_iter()
+        .map(|(target, until)| SilenceJson { target, until })
+        .collect::<Vec<SilenceJson>>();
+    active.sort_unstable_by_key(|s| s.until);
+    active
+}
+
+fn json<T: Serialize>(body: &T) -> Response<Body> {
+    match serde_json::to_string(body) {
+        Ok(b) => {
+            let mut resp = Response::new(Body::from(b));
+            resp.headers_mut()
+                .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+            resp
+        }
+        Err(e) => text(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("encode error {}", e),
+        ),
+    }
+}
+
+fn text(status: StatusCode, body: &str) -> Response<Body> {
+    let mut resp = Response::new(Body::from(body.to_string()));
+    *resp.status_mut() = status;
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use cex_dex_monitor::{AlertKind, LatencyConfig};
+    use hyper::{Body, Request, StatusCode};
+    use rust_decimal_macros::dec;
+
+    use super::{constant_time_eq, get, handle, Context};
+    use crate::{
+        latency::{self, StateLatency},
+        routing::{SilenceTarget, Silences},
+        scheduler,
+        testutil::state,
+    };
+
+    fn ctx() -> Arc<Context> {
+        Arc::new(Context {
+            token: "secret".to_string(),
+            silences: Arc::new(Silences::default()),
+        })
+    }
+
+    async fn call(ctx: &Arc<Context>, method: &str, uri: &str) -> (StatusCode, String) {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("Authorization", "Bearer secret")
+            .body(Body::empty())
+            .unwrap();
+        let resp = handle(ctx.clone(), req).await.unwrap();
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, String::from_utf8_lossy(&body).to_string())
+    }
+
+    #[tokio::test]
+    async fn requests_without_token_are_rejected() {
+        let req = Request::builder()
+            .uri("/balances")
+            .header("Authorization", "Bearer wrong")
+            .body(Body::empty())
+            .unwrap();
+        let resp = handle(ctx(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+
+    #[tokio::test]
+    async fn reports_are_filtered_by_env() {
+        let ctx = ctx();
+        let r = get();
+        r.set_balances(
+            "admin-test",
+            &HashMap::from([("KNC".to_string(), dec!(1.5))]),
+            Some(dec!(0.75)),
+        );
+        let now = scheduler::now();
+        r.set_last_diff(
+            "admin-test",
+            AlertKind::BalanceDiff1h,
+            now,
+            now,
+            &[("KNC".to_string(), dec!(-0.5))],
+        );
+        r.add_state(
+            "admin-test",
+            &state(),
+            StateLatency {
+                dex_mine_ms: vec![1500],
+                ..Default::default()
+            },
+        );
+
+        let (status, body) = call(&ctx, "GET", "/balances?env=admin-test").await;
+        assert_eq!(status, StatusCode::OK);
+        let v: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(v.as_object().unwrap().len(), 1);
+        assert_eq!(v["admin-test"]["balances"]["KNC"], "1.5");
+        assert_eq!(v["admin-test"]["usd_value"], "0.75");
+
+        let (_, body) = call(&ctx, "GET", "/diff?env=admin-test").await;
+        let v: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(v["admin-test"]["kind"], "balance-diff-1h");
+        assert_eq!(v["admin-test"]["changes"]["KNC"], "-0.5");
+
+        let (_, body) = call(&ctx, "GET", "/states?env=admin-test&limit=1").await;
+        let v: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(v[0]["state_id"], "s1");
+        assert_eq!(v[0]["latency"]["dex_mine_ms"][0], 1500);
+    }
+
+    #[tokio::test]
+    async fn latency_percentiles_per_env() {
+        let ctx = ctx();
+        latency::get().record(
+            "admin-latency-test",
+            &StateLatency {
+                cex_fill_ms: vec![100, 300],
+                ..Default::default()
+            },
+            scheduler::now(),
+            &LatencyConfig::default(),
+        );
+
+        let (status, body) = call(&ctx, "GET", "/latency?env=admin-latency-test").await;
+        assert_eq!(status, StatusCode::OK);
+        let v: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(v.as_object().unwrap().len(), 1);
+        assert_eq!(v["admin-latency-test"]["cex_fill_ms"]["samples"], 2);
+        assert_eq!(v["admin-latency-test"]["cex_fill_ms"]["p95"], 300.0);
+        assert_eq!(v["admin-latency-test"]["gas"]["p50"], 0.0);
+    }
+
+    #[tokio::test]
+    async fn snapshot_is_triggered_for_monitored_envs_only() {
+        let ctx = ctx();
+        let trigger = get().snapshot_trigger("admin-snapshot-test");
+
+        let (status, _) = call(&ctx, "POST", "/snapshot?env=admin-snapshot-test").await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        // the permit is kept until the monitor waits
+        trigger.notify().notified().await;
+
+        let (status, _) = call(&ctx, "POST", "/snapshot?env=unknown").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // a replaced monitor keeps the trigger of its env, a stopped one drops it
+        let replacement = get().snapshot_trigger("admin-snapshot-test");
+        drop(trigger);
+        let (status, _) = call(&ctx, "POST", "/snapshot?env=admin-snapshot-test").await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        drop(replacement);
+        let (status, _) = call(&ctx, "POST", "/snapshot?env=admin-snapshot-test").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn silences_are_set_listed_and_lifted() {
+        let ctx = ctx();
+
+        let (status, body) = call(&ctx, "POST", "/silence?env=prod&minutes=30").await;
+        assert_eq!(status, StatusCode::OK);
+        let v: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(v[0]["target"]["kind"], "env");
+        assert_eq!(v[0]["target"]["name"], "prod");
+        assert_eq!(
+            ctx.silences.active(scheduler::now())[0].0,
+            SilenceTarget::Env("prod".to_string())
+        );
+
+        let (status, _) = call(&ctx, "POST", "/silence?channel=c&minutes=0").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let too_long = format!("/silence?channel=c&minutes={}", i64::MAX);
+        let (status, _) = call(&ctx, "POST", &too_long).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = call(&ctx, "POST", "/silence?channel=c&minutes=9999999999999").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = call(&ctx, "DELETE", "/silence?env=prod").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(ctx.silences.active(scheduler::now()).is_empty());
+
+        // values are percent decoded
+        let (status, _) = call(&ctx, "POST", "/silence?channel=ops%20a%3Db&minutes=5").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            ctx.silences.active(scheduler::now())[0].0,
+            SilenceTarget::Channel("ops a=b".to_string())
+        );
+    }
+}
@@ -0,0 +1,563 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use cex_dex_monitor::{AlertKind, AlertQueueConfig};
+use futures_util::future::join_all;
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinHandle,
+    time::Instant,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+use crate::{notifier::Notifier, slackclient::message::Message};
+
+// slack rejects messages with more blocks
+const MAX_BLOCKS: usize = 50;
+const MINUTE: Duration = Duration::from_secs(60);
+// a send taking longer is a failed attempt. The channels are sent concurrently, a
+// hung notifier holds back the other channels' alerts queued during its send only
+const SEND_TIMEOUT: Duration = Duration::from_secs(15);
+
+struct Outgoing {
+    notifier: Arc<dyn Notifier>,
+    channel: String,
+    kind: AlertKind,
+    msg: Message,
+}
+
+impl Outgoing {
+    async fn send(&self, channel: &str) -> anyhow::Result<()> {
+        tokio::time::timeout(
+            SEND_TIMEOUT,
+            self.notifier.send(channel, self.kind, &self.msg),
+        )
+        .await
+        .map_err(|_| anyhow::format_err!("send timed out after {:?}", SEND_TIMEOUT))?
+    }
+}
+
+// Queue is a Notifier that only enqueues, a single worker delivers the alerts to the
+// inner notifier with batching, dedup, per-channel rate limits and retries
+#[derive(Clone)]
+pub struct Queue {
+    tx: mpsc::Sender<Outgoing>,
+    inner: Arc<dyn Notifier>,
+}
+
+impl Queue {
+    // the worker sends what is left once stop is cancelled, then ends
+    pub fn start(
+        cfg: &AlertQueueConfig,
+        inner: Arc<dyn Notifier>,
+        stop: CancellationToken,
+    ) -> (Queue, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(cfg.capacity.max(1));
+        let worker = Worker::new(cfg);
+        (Queue { tx, inner }, tokio::spawn(worker.run(rx, stop)))
+    }
+
+    // same worker, new alerts go to inner while the queued ones keep their notifier
+    pub fn with_notifier(&self, inner: Arc<dyn Notifier>) -> Queue {
+        Queue {
+            tx: self.tx.clone(),
+            inner,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Queue {
+    fn has_channel(&self, channel: &str) -> bool {
+        self.inner.has_channel(channel)
+    }
+
+    async fn send(&self, channel: &str, kind: AlertKind, msg: &Message) -> anyhow::Result<()> {
+        let out = Outgoing {
+            notifier: self.inner.clone(),
+            channel: channel.to_string(),
+            kind,
+            msg: msg.clone(),
+        };
+        self.tx.try_send(out).map_err(|e| match e {
+            TrySendError::Full(_) => anyhow::format_err!("alert queue full"),
+            TrySendError::Closed(_) => anyhow::format_err!("alert queue stopped"),
+        })
+    }
+}
+
+struct Pending {
+    out: Outgoing,
+    attempts: u32,
+}
+
+#[derive(Default)]
+struct Channel {
+    // state done alerts waiting for the end of the batch window
+    batch: Vec<Outgoing>,
+    batch_deadline: Option<Instant>,
+    ready: VecDeque<Pending>,
+    // send times, the oldest ones are dropped after a minute
+    sent: VecDeque<Instant>,
+    // set after a failed post
+    retry_at: Option<Instant>,
+}
+
+// rate limit and retries of each channel
+#[derive(Clone, Copy)]
+struct Limits {
+    max_per_minute: usize,
+    max_retries: u32,
+    retry_base: Duration,
+    retry_max: Duration,
+}
+
+struct Worker {
+    batch_window: Duration,
+    max_batch: usize,
+    dedup_window: Duration,
+    limits: Limits,
+    channels: HashMap<String, Channel>,
+    // fingerprint of channel and message -> last time it was queued
+    recent: HashMap<u64, Instant>,
+}
+
+impl Worker {
+    fn new(cfg: &AlertQueueConfig) -> Worker {
+        Worker {
+            batch_window: Duration::from_secs(cfg.batch_window_secs),
+            max_batch: cfg.max_batch,
+            dedup_window: Duration::from_secs(cfg.dedup_window_secs),
+            limits: Limits {
+                max_per_minute: cfg.max_per_minute,
+                max_retries: cfg.max_retries,
+                retry_base: Duration::from_millis(cfg.retry_base_ms),
+                retry_max: Duration::from_millis(cfg.retry_max_ms),
+            },
+            channels: HashMap::new(),
+            recent: HashMap::new(),
+        }
+    }
+
+    async fn run(mut self, mut rx: mpsc::Receiver<Outgoing>, stop: CancellationToken) {
+        loop {
+            let wake = self.next_wake();
+            tokio::select! {
+                _ = stop.cancelled() => break,
+                out = rx.recv() => match out {
+                    Some(out) => {
+                        self.push(out, Instant::now());
+                        // every queued alert goes out in this round
+                        while let Ok(out) = rx.try_recv() {
+                            self.push(out, Instant::now());
+                        }
+                    }
+                    None => break,
+                },
+                _ = sleep_until(wake) => {}
+            }
+            self.deliver(Instant::now()).await;
+        }
+
+        rx.close();
+        while let Ok(out) = rx.try_recv() {
+            self.push(out, Instant::now());
+        }
+        self.flush().await;
+    }
+
+    fn push(&mut self, out: Outgoing, now: Instant) {
+        if !self.dedup_window.is_zero() {
+            let window = self.dedup_window;
+            self.recent.retain(|_, t| now.duration_since(*t) < window);
+            if self.recent.insert(fingerprint(&out), now).is_some() {
+                debug!(kind = ?out.kind, channel = %out.channel, "duplicate alert dropped");
+                return;
+            }
+        }
+
+        let batching =
+            out.kind == AlertKind::StateDone && !self.batch_window.is_zero() && self.max_batch > 1;
+        let c = self.channels.entry(out.channel.clone()).or_default();
+        if !batching {
+            c.ready.push_back(Pending { out, attempts: 0 });
+            return;
+        }
+
+        c.batch.push(out);
+        c.batch_deadline.get_or_insert(now + self.batch_window);
+        if c.batch.len() >= self.max_batch {
+            c.flush_batch();
+        }
+    }
+
+    // earliest time something can be sent, none when nothing is waiting
+    fn next_wake(&self) -> Option<Instant> {
+        self.channels
+            .values()
+            .flat_map(|c| {
+                let mut ready_at = None;
+                if !c.ready.is_empty() {
+                    let mut t = c.retry_at.unwrap_or_else(Instant::now);
+                    let max_per_minute = self.limits.max_per_minute;
+                    if max_per_minute > 0 && c.sent.len() >= max_per_minute {
+                        t = t.max(c.sent[c.sent.len() - max_per_minute] + MINUTE);
+                    }
+                    ready_at = Some(t);
+                }
+                [c.batch_deadline, ready_at]
+            })
+            .flatten()
+            .min()
+    }
+
+    async fn deliver(&mut self, now: Instant) {
+        let limits = self.limits;
+        join_all(
+            self.channels
+                .iter_mut()
+                .map(|(channel, c)| c.deliver(channel, now, limits)),
+        )
+        .await;
+    }
+
+    // one attempt for everything left, without rate limits
+    async fn flush(&mut self) {
+        join_all(
+            self.channels
+                .iter_mut()
+                .map(|(channel, c)| c.flush(channel)),
+        )
+        .await;
+    }
+}
+
+impl Channel {
+    // sends the ready alerts in order until the rate limit, or a failure that
+    // retries the alert later
+    async fn deliver(&mut self, channel: &str, now: Instant, limits: Limits) {
+        if self.batch_deadline.is_some_and(|t| t <= now) {
+            self.flush_batch();
+        }
+
+        while !self.ready.is_empty() {
+            if self.retry_at.is_some_and(|t| t > now) {
+                break;
+            }
+            while self
+                .sent
+                .front()
+                .is_some_and(|t| now.duration_since(*t) >= MINUTE)
+            {
+                self.sent.pop_front();
+            }
+            if limits.max_per_minute > 0 && self.sent.len() >= limits.max_per_minute {
+                break;
+            }
+
+            let mut p = self.ready.pop_front().unwrap();
+            match p.out.send(channel).await {
+                Ok(_) => {
+                    self.sent.push_back(Instant::now());
+                    self.retry_at = None;
+                }
+                Err(e) => {
+                    p.attempts += 1;
+                    if p.attempts > limits.max_retries {
+                        warn!(
+                            kind = ?p.out.kind,
+                            %channel,
+                            error = %e,
+                            attempts = p.attempts,
+                            "send alert failed, dropped"
+                        );
+                        continue;
+                    }
+                    let backoff = limits
+                        .retry_base
+                        .saturating_mul(2u32.saturating_pow(p.attempts - 1))
+                        .min(limits.retry_max);
+                    warn!(
+                        kind = ?p.out.kind,
+                        %channel,
+                        error = %e,
+                        ?backoff,
+                        "send alert failed, retrying"
+                    );
+                    self.retry_at = Some(Instant::now() + backoff);
+                    self.ready.push_front(p);
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn flush(&mut self, channel: &str) {
+        self.flush_batch();
+        for p in self.ready.drain(..) {
+            if let Err(e) = p.out.send(channel).await {
+                warn!(kind = ?p.out.kind, %channel, error = %e, "send alert failed");
+            }
+        }
+    }
+
+    fn flush_batch(&mut self) {
+        self.batch_deadline = None;
+        if self.batch.is_empty() {
+            return;
+        }
+        let out = digest(std::mem::take(&mut self.batch));
+        self.ready.push_back(Pending { out, attempts: 0 });
+    }
+}
+
+// several alerts as one message, a single alert is kept as is
+fn digest(mut items: Vec<Outgoing>) -> Outgoing {
+    if items.len() == 1 {
+        return items.pop().unwrap();
+    }
+
+    let title = format!("{} STATES DONE", items.len());
+    let text = format!(
+        "*****\n*{}*\n\n{}",
+        title,
+        items
+            .iter()
+            .map(|o| o.msg.text.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n\n")
+    );
+    let mut msg = Message::builder(text.clone()).header(&title).build();
+    for o in items.iter() {
+        msg.blocks.push(crate::slackclient::message::Block::Divider);
+        msg.blocks.extend(o.msg.blocks.iter().cloned());
+        // the colors of the attachments are lost, their rows stay next to their state
+        for a in o.msg.attachments.iter() {
+            msg.blocks.extend(a.blocks.iter().cloned());
+        }
+    }
+    if msg.blocks.len() > MAX_BLOCKS {
+        msg = Message::plain(text);
+    }
+
+    let first = items.swap_remove(0);
+    Outgoing {
+        notifier: first.notifier,
+        channel: first.channel,
+        kind: first.kind,
+        msg,
+    }
+}
+
+fn fingerprint(out: &Outgoing) -> u64 {
+    let mut h = DefaultHasher::new();
+    out.channel.hash(&mut h);
+    serde_json::to_string(&out.msg)
+        .unwrap_or_default()
+        .hash(&mut h);
+    h.finish()
+}
+
+async fn sleep_until(t: Option<Instant>) {
+    match t {
+        Some(t) => tokio::time::sleep_until(t).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use cex_dex_monitor::{AlertKind, AlertQueueConfig};
+    use tokio::time::Instant;
+    use tokio_util::sync::CancellationToken;
+
+    use super::Queue;
+    use crate::{notifier::Notifier, slackclient::message::Message};
+
+    // records (channel, text, time), hangs on the first `hangs` posts and fails the
+    // next `failures` ones
+    #[derive(Default)]
+    struct FlakySink {
+        sent: Mutex<Vec<(String, String, Instant)>>,
+        failures: AtomicUsize,
+        hangs: AtomicUsize,
+    }
+
+    impl FlakySink {
+        fn texts(&self) -> Vec<String> {
+            let sent = self.sent.lock().unwrap();
+            sent.iter().map(|(_, t, _)| t.clone()).collect()
+        }
+    }
+
+    #[async_trait]
+    impl Notifier for FlakySink {
+        fn has_channel(&self, _: &str) -> bool {
+            true
+        }
+
+        async fn send(&self, channel: &str, _: AlertKind, msg: &Message) -> anyhow::Result<()> {
+            if self
+                .hangs
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |h| h.checked_sub(1))
+                .is_ok()
+            {
+                std::future::pending::<()>().await;
+            }
+            if self
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |f| f.checked_sub(1))
+                .is_ok()
+            {
+                return Err(anyhow::format_err!("webhook down"));
+            }
+            self.sent
+                .lock()
+                .unwrap()
+                .push((channel.to_string(), msg.text.clone(), Instant::now()));
+            Ok(())
+        }
+    }
+
+    fn start(cfg: AlertQueueConfig, sink: &Arc<FlakySink>) -> Queue {
+        Queue::start(&cfg, sink.clone(), CancellationToken::new()).0
+    }
+
+    async fn send(q: &Queue, kind: AlertKind, text: &str) {
+        q.send("a", kind, &Message::plain(text.to_string()))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn state_done_alerts_are_batched_into_a_digest() {
+        let sink = Arc::new(FlakySink::default());
+        let q = start(AlertQueueConfig::default(), &sink);
+
+        for s in ["s1", "s2", "s3"] {
+            send(&q, AlertKind::StateDone, s).await;
+        }
+        send(&q, AlertKind::Error, "down").await;
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(sink.texts(), vec!["down".to_string()]);
+
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert_eq!(
+            sink.texts(),
+            vec![
+                "down".to_string(),
+                "*****\n*3 STATES DONE*\n\ns1\n\ns2\n\ns3".to_string()
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn duplicates_are_dropped_and_channels_rate_limited() {
+        let sink = Arc::new(FlakySink::default());
+        let cfg = AlertQueueConfig {
+            max_per_minute: 2,
+            ..Default::default()
+        };
+        let q = start(cfg, &sink);
+        let t0 = Instant::now();
+
+        for text in ["x", "x", "y", "z"] {
+            send(&q, AlertKind::Error, text).await;
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(sink.texts(), vec!["x".to_string(), "y".to_string()]);
+
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        let sent = sink.sent.lock().unwrap();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[2].1, "z");
+        assert_eq!(sent[2].2 - t0, Duration::from_secs(60));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_posts_are_retried_with_backoff() {
+        let sink = Arc::new(FlakySink::default());
+        sink.failures.store(2, Ordering::SeqCst);
+        let q = start(AlertQueueConfig::default(), &sink);
+        let t0 = Instant::now();
+
+        send(&q, AlertKind::Error, "down").await;
+
+        // fails at 0s and 1s, sent at 1s + 2s
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let sent = sink.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].2 - t0, Duration::from_secs(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hung_posts_time_out_and_are_retried() {
+        let sink = Arc::new(FlakySink::default());
+        sink.hangs.store(1, Ordering::SeqCst);
+        let q = start(AlertQueueConfig::default(), &sink);
+        let t0 = Instant::now();
+
+        send(&q, AlertKind::Error, "down").await;
+
+        // times out at 15s, sent at 15s + 1s
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        let sent = sink.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].2 - t0, Duration::from_secs(16));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hung_channel_does_not_hold_back_the_others() {
+        let sink = Arc::new(FlakySink::default());
+        sink.hangs.store(1, Ordering::SeqCst);
+        let q = start(AlertQueueConfig::default(), &sink);
+        let t0 = Instant::now();
+
+        send(&q, AlertKind::Error, "hung").await;
+        q.send("b", AlertKind::Error, &Message::plain("b".to_string()))
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        let sent = sink.sent.lock().unwrap();
+        let at = |channel: &str| {
+            sent.iter()
+                .find(|(c, _, _)| c.eq(channel))
+                .map(|(_, _, t)| *t - t0)
+                .unwrap()
+        };
+        assert_eq!(at("b"), Duration::ZERO);
+        assert_eq!(at("a"), Duration::from_secs(16));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stop_sends_the_pending_batch() {
+        let sink = Arc::new(FlakySink::default());
+        let stop = CancellationToken::new();
+        let (q, worker) = Queue::start(&AlertQueueConfig::default(), sink.clone(), stop.clone());
+
+        send(&q, AlertKind::StateDone, "s1").await;
+        tokio::task::yield_now().await;
+        stop.cancel();
+        worker.await.unwrap();
+
+        assert_eq!(sink.texts(), vec!["s1".to_string()]);
+    }
+}
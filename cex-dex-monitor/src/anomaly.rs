@@ -0,0 +1,226 @@
+use cex_dex_monitor::AnomalyConfig;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+use crate::{cexdexclient::dto::StateData, slackclient::message::Message};
+
+// Finding is one broken execution rule of a state
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub detail: String,
+}
+
+pub fn check(state: &StateData, quote: &str, cfg: &AnomalyConfig) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    findings.extend(check_failed_txs(state));
+    findings.extend(check_dex_slippage(state, cfg));
+    findings.extend(check_p2_price(state, quote, cfg));
+    findings.extend(check_base_balance(state, cfg));
+    findings
+}
+
+fn check_failed_txs(state: &StateData) -> Vec<Finding> {
+    let txs = match &state.p2_dex_txs {
+        None => return Vec::new(),
+        Some(txs) => txs,
+    };
+
+    txs.iter()
+        .filter(|tx| !tx.status.eq("EXECUTED"))
+        .map(|tx| Finding {
+            rule: "DEX TX NOT EXECUTED",
+            detail: format!(
+                "tx {} status {}, nonce {}, {} {} -> {}",
+                tx.tx_hash, tx.status, tx.nonce, tx.amount_in, tx.token_in, tx.token_out
+            ),
+        })
+        .collect()
+}
+
+// amount out shortfall of each executed tx against the allowed slippage of the state
+fn check_dex_slippage(state: &StateData, cfg: &AnomalyConfig) -> Vec<Finding> {
+    let txs = match &state.p2_dex_txs {
+        None => return Vec::new(),
+        Some(txs) => txs,
+    };
+    let allowed = state.slippage / cfg.slippage_scale;
+
+    txs.iter()
+        .filter(|tx| tx.status.eq("EXECUTED") && tx.estimated_amount_out > Decimal::ZERO)
+        .filter_map(|tx| {
+            let actual = ((tx.estimated_amount_out - tx.actual_amount_out)
+                / tx.estimated_amount_out)
+                .to_f64()
+                .unwrap_or_default();
+            if actual <= allowed {
+                return None;
+            }
+            Some(Finding {
+                rule: "DEX SLIPPAGE",
+                detail: format!(
+                    "tx {} slippage {:.6} > allowed {:.6}, estimated_amount_out {}, actual_amount_out {}, estimated_price {}, actual_price {}",
+                    tx.tx_hash,
+                    actual,
+                    allowed,
+                    tx.estimated_amount_out,
+                    tx.actual_amount_out,
+                    tx.estimated_price,
+                    tx.actual_price
+                ),
+            })
+        })
+        .collect()
+}
+
+// p2 (cex and dex fills together) trades the opposite side of p1, worse means
+// selling lower than p1 bought, or buying higher than p1 sold
+fn check_p2_price(state: &StateData, quote: &str, cfg: &AnomalyConfig) -> Option<Finding> {
+    let p1_price = state.get_cex_price(&state.p1_cex_orders);
+    let p2_base = state.p2_sum_base_filled() + state.p2_sum_token_filled(&state.token);
+    let p2_quote = state.p2_sum_quote_filled() + state.p2_sum_token_filled(&quote.to_string());
+    if p1_price == 0.0 || p2_base.is_zero() {
+        return None;
+    }
+    let p2_price = (p2_quote / p2_base).to_f64().unwrap_or_default();
+
+    let worse_by = match state.side.to_uppercase().as_str() {
+        "BUY" => (p1_price - p2_price) / p1_price,
+        "SELL" => (p2_price - p1_price) / p1_price,
+        _ => return None,
+    };
+    if worse_by <= cfg.max_p2_price_worse {
+        return None;
+    }
+
+    Some(Finding {
+        rule: "P2 PRICE WORSE THAN P1",
+        detail: format!(
+            "side {}, p1 price {}, p2 price {}, worse by {:.6} > {:.6}, p1_price_diff {}, p1_profitable_threshold {}",
+            state.side,
+            p1_price,
+            p2_price,
+            worse_by,
+            cfg.max_p2_price_worse,
+            state.p1_price_diff,
+            state.p1_profitable_threshold
+        ),
+    })
+}
+
+fn check_base_balance(state: &StateData, cfg: &AnomalyConfig) -> Option<Finding> {
+    let p1_base = state.p1_sum_base_filled();
+    let p2_cex_base = state.p2_sum_base_filled();
+    let p2_dex_base = state.p2_sum_token_filled(&state.token);
+    let p2_base = p2_cex_base + p2_dex_base;
+    if p1_base.is_zero() && p2_base.is_zero() {
+        return None;
+    }
+
+    let imbalance = ((p1_base - p2_base).abs() / p1_base.max(p2_base))
+        .to_f64()
+        .unwrap_or_default();
+    if imbalance <= cfg.max_base_imbalance {
+        return None;
+    }
+
+    Some(Finding {
+        rule: "UNBALANCED BASE AMOUNT",
+        detail: format!(
+            "p1 base {}, p2 base {} (cex {}, dex {}), imbalance {:.6} > {:.6}",
+            p1_base, p2_base, p2_cex_base, p2_dex_base, imbalance, cfg.max_base_imbalance
+        ),
+    })
+}
+
+pub fn build_message(state: &StateData, env: &str, findings: &[Finding], mention: &str) -> Message {
+    let mut fallback = format!(
+        "*****\n*EXECUTION ANOMALY*\n{}\n> ENV: {}\nSTATE_ID: {}\nTOKEN: {}\nSIDE: {}\n",
+        mention, env, state.state_id, state.token, state.side
+    );
+    for f in findings {
+        fallback.push_str(&format!("- {}: {}\n", f.rule, f.detail));
+    }
+    fallback.push_str("*****");
+
+    let mut b = Message::builder(fallback)
+        .header("EXECUTION ANOMALY")
+        .markdown(mention)
+        .fields(vec![
+            ("ENV", env.to_string()),
+            ("STATE_ID", state.state_id.clone()),
+            ("TOKEN", state.token.clone()),
+            ("SIDE", state.side.clone()),
+        ]);
+    for f in findings {
+        b = b.markdown(&format!("*{}*\n{}", f.rule, f.detail));
+    }
+    b.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use cex_dex_monitor::AnomalyConfig;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    use super::check;
+    use crate::testutil::{order, state, tx};
+
+    fn rules(s: &crate::cexdexclient::dto::StateData) -> Vec<&'static str> {
+        check(s, "USDT", &AnomalyConfig::default())
+            .into_iter()
+            .map(|f| f.rule)
+            .collect()
+    }
+
+    // p1 buys 10 KNC at 2.0 on cex, p2 sells them at 2.1 on dex
+    fn good_state() -> crate::cexdexclient::dto::StateData {
+        let mut s = state();
+        s.p2_cex_orders = None;
+        s.slippage = 0.01;
+        s
+    }
+
+    #[test]
+    fn good_execution_has_no_finding() {
+        assert!(rules(&good_state()).is_empty());
+    }
+
+    #[test]
+    fn failed_tx_is_reported() {
+        let mut s = good_state();
+        let mut failed = tx();
+        failed.status = "FAILED".to_string();
+        failed.amount_in = Decimal::ZERO;
+        s.p2_dex_txs.as_mut().unwrap().push(failed);
+
+        assert_eq!(rules(&s), vec!["DEX TX NOT EXECUTED"]);
+    }
+
+    #[test]
+    fn slippage_beyond_allowed_is_reported() {
+        let mut s = good_state();
+        // 21 expected, 20.5 received: 2.4% against 1% allowed
+        s.p2_dex_txs.as_mut().unwrap()[0].actual_amount_out = dec!(20.5);
+
+        assert_eq!(rules(&s), vec!["DEX SLIPPAGE"]);
+    }
+
+    #[test]
+    fn worse_p2_price_and_unbalanced_base_are_reported() {
+        let mut s = good_state();
+        // p2 sells 5 KNC on dex for 9 USDT and 10 KNC on cex for 18 USDT: 1.8 < 2.0
+        let dex = &mut s.p2_dex_txs.as_mut().unwrap()[0];
+        dex.amount_in = dec!(5);
+        dex.estimated_amount_out = dec!(9);
+        dex.actual_amount_out = dec!(9);
+        let mut cex = order("USDT", Decimal::ZERO);
+        cex.filled_quote_amount = dec!(18);
+        s.p2_cex_orders = Some(vec![cex]);
+
+        assert_eq!(
+            rules(&s),
+            vec!["P2 PRICE WORSE THAN P1", "UNBALANCED BASE AMOUNT"]
+        );
+    }
+}
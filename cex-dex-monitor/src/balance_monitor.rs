@@ -1,59 +1,265 @@
-use crate::*;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use async_trait::async_trait;
+use cex_dex_monitor::{AssetsConfig, CexDexClientConfig, SchedulerConfig};
+use rust_decimal::Decimal;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{
+    admin,
+    cexdexclient::error::{self, ApiError},
+    outage::OutageTracker,
+    pricing::{PriceProvider, Valuation},
+    rebalance::{RebalanceEvent, RebalanceTracker},
+    routing::{Alert, Router},
+    scheduler::Interval,
+    slackclient::message::Message,
+    store::{BalanceSnapshot, SnapshotWindow},
+    timeline::Timeline,
+    *,
+};
+
+// Balances is the balance (asset -> amount) of every venue of an env,
+// venues are named after their api path, e.g. cex/binance, dex/polygon
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Balances {
+    pub venues: BTreeMap<String, HashMap<String, Decimal>>,
+    // venues moving funds, they have no balance
+    pub rebalancing: BTreeSet<String>,
+}
+
+impl Balances {
+    pub fn add(&mut self, venue: &str, asset: String, amount: Decimal) {
+        *self
+            .venues
+            .entry(venue.to_string())
+            .or_default()
+            .entry(asset)
+            .or_default() += amount;
+    }
+
+    pub fn total(&self) -> HashMap<String, Decimal> {
+        let mut total = HashMap::<String, Decimal>::new();
+        for balance in self.venues.values() {
+            for (asset, amount) in balance.iter() {
+                *total.entry(asset.clone()).or_default() += amount;
+            }
+        }
+        total
+    }
+}
+
+// BalanceSource returns the balances per venue of an env
+#[async_trait]
+pub trait BalanceSource: Send + Sync {
+    async fn fetch_balance(&self) -> anyhow::Result<Balances>;
+}
 
 pub struct Service<'a> {
     // input
     env: String,
-    epsilon: f64,
+    assets: AssetsConfig,
 
     // data
-    current_balance: HashMap<String, f64>,
+    venue_balances: Balances,
+    valuation: Valuation,
+    current_balance: HashMap<String, Decimal>,
     current_balance_update: chrono::DateTime<chrono::Utc>,
-    previous_balance: HashMap<String, f64>,
+    current_value: Option<Decimal>,
+    previous_balance: HashMap<String, Decimal>,
     previous_balance_update: chrono::DateTime<chrono::Utc>,
+    previous_value: Option<Decimal>,
+
+    // schedule
+    fetch_interval: Interval,
+    check_interval: Interval,
+    outages: OutageTracker,
+    rebalances: RebalanceTracker,
+    // notified by the admin api, the snapshot is sent at the next check
+    snapshot_trigger: admin::SnapshotTrigger,
+    snapshot_requested: bool,
 
     // clients
-    cex_dex: Vec<cexdexclient::client::CexDexClient>,
-    slack: &'a slackclient::client::Client,
+    source: Box<dyn BalanceSource>,
+    prices: Option<Arc<dyn PriceProvider>>,
+    timeline: Option<Arc<Timeline>>,
+    alerts: &'a Router,
+    store: Arc<dyn store::Store>,
 }
 
 impl<'a> Service<'a> {
     pub fn new(
         env: String,
-        epsilon: f64,
-        cex_dex: Vec<cexdexclient::client::CexDexClient>,
-        slack: &'a slackclient::client::Client,
-    ) -> Service {
-        if cex_dex.is_empty() {
-            panic!("empty cex_dex list")
-        }
-
+        assets: &AssetsConfig,
+        source: Box<dyn BalanceSource>,
+        alerts: &'a Router,
+        store: Arc<dyn store::Store>,
+        schedule: &SchedulerConfig,
+        client: &CexDexClientConfig,
+    ) -> Service<'a> {
+        let snapshot_trigger = admin::get().snapshot_trigger(&env);
         Self {
             env,
-            epsilon,
+            assets: assets.clone(),
+            venue_balances: Balances::default(),
+            valuation: Valuation::default(),
             current_balance: HashMap::new(),
-            current_balance_update: chrono::Utc::now(),
+            current_balance_update: scheduler::now(),
+            current_value: None,
             previous_balance: HashMap::new(),
-            previous_balance_update: chrono::Utc::now(),
-            cex_dex,
-            slack,
+            previous_balance_update: scheduler::now(),
+            previous_value: None,
+            fetch_interval: Interval::from(&schedule.balance_fetch),
+            check_interval: Interval::from(&schedule.balance_check),
+            outages: OutageTracker::new(Duration::from_secs(client.outage_alert_after_secs)),
+            rebalances: RebalanceTracker::new(Duration::from_secs(
+                client.rebalancing_alert_after_secs,
+            )),
+            snapshot_trigger,
+            snapshot_requested: false,
+            source,
+            prices: None,
+            timeline: None,
+            alerts,
+            store,
+        }
+    }
+
+    // values the balances and diffs in usd
+    pub fn with_prices(mut self, prices: Arc<dyn PriceProvider>) -> Service<'a> {
+        self.prices = Some(prices);
+        self
+    }
+
+    // keeps every fetched balance
+    pub fn with_timeline(mut self, timeline: Arc<Timeline>) -> Service<'a> {
+        self.timeline = Some(timeline);
+        self
+    }
+
+    // an empty valuation on error, the messages then only show amounts
+    async fn fetch_valuation(&self, balances: &HashMap<String, Decimal>) -> Valuation {
+        let prices = match &self.prices {
+            None => return Valuation::default(),
+            Some(p) => p,
+        };
+        let mut assets = balances.keys().cloned().collect::<Vec<String>>();
+        assets.sort_unstable();
+        match prices.usd_prices(&assets).await {
+            Ok(p) => Valuation::new(p),
+            Err(e) => {
+                warn!(error = %e, "fetch usd prices failed");
+                Valuation::default()
+            }
         }
     }
 
-    pub async fn monitor_balance(&mut self) {
+    // restore the diff baselines saved before the last restart
+    fn restore_snapshots(&mut self) {
+        match self
+            .store
+            .load_balance_snapshot(&self.env, SnapshotWindow::Hour)
+        {
+            Ok(Some(s)) => {
+                self.current_balance = s.balances;
+                self.current_balance_update = s.updated_at;
+                self.current_value = s.usd_value;
+            }
+            Ok(None) => {}
+            Err(e) => warn!(error = %e, "load 1h balance snapshot failed"),
+        }
+        match self
+            .store
+            .load_balance_snapshot(&self.env, SnapshotWindow::Day)
+        {
+            Ok(Some(s)) => {
+                self.previous_balance = s.balances;
+                self.previous_balance_update = s.updated_at;
+                self.previous_value = s.usd_value;
+            }
+            Ok(None) => {}
+            Err(e) => warn!(error = %e, "load 24h balance snapshot failed"),
+        }
+        info!(
+            snapshot_1h = %self.current_balance_update,
+            snapshot_24h = %self.previous_balance_update,
+            "restored balance snapshots"
+        );
+    }
+
+    fn save_snapshot(&self, window: SnapshotWindow) {
+        let snapshot = match window {
+            SnapshotWindow::Hour => BalanceSnapshot {
+                balances: self.current_balance.clone(),
+                updated_at: self.current_balance_update,
+                usd_value: self.current_value,
+            },
+            SnapshotWindow::Day => BalanceSnapshot {
+                balances: self.previous_balance.clone(),
+                updated_at: self.previous_balance_update,
+                usd_value: self.previous_value,
+            },
+        };
+        if let Err(e) = self
+            .store
+            .save_balance_snapshot(&self.env, window, &snapshot)
+        {
+            warn!(?window, error = %e, "save balance snapshot failed");
+        }
+    }
+
+    // runs until shutdown is cancelled, a started check is always finished
+    pub async fn monitor_balance(&mut self, shutdown: &CancellationToken) {
+        self.restore_snapshots();
+
         let fetch_limit = 1;
         loop {
-            let mut balance = HashMap::<String, f64>::new();
+            let mut balance = HashMap::<String, Decimal>::new();
             let mut fetch_count = fetch_limit;
             while fetch_count > 0 {
-                thread::sleep(Duration::from_secs(
-                    20 + (chrono::Utc::now().timestamp() % 5) as u64,
-                ));
-                match self.fetch_balance().await {
+                if !scheduler::sleep(&self.fetch_interval, shutdown).await {
+                    info!("balance monitor stopped");
+                    return;
+                }
+                let fetched = self.source.fetch_balance().await;
+                // an api answering that it is rebalancing is not down
+                let reachable = fetched
+                    .as_ref()
+                    .map_or_else(|e| !error::is_outage(e), |_| true);
+                if let Some(o) = self.outages.record(reachable, scheduler::now()) {
+                    let last_error = fetched.as_ref().err().map(error::describe);
+                    self.send_error_msg(o.message(&self.env, "fetch balance", last_error))
+                        .await;
+                }
+                match fetched {
                     Err(e) => {
-                        println!("fetch balance error {}", e);
+                        warn!(error = %e, "fetch balance failed");
                         fetch_count = fetch_limit;
                     }
-                    Ok(b) => {
+                    Ok(venues) => {
+                        for e in self
+                            .rebalances
+                            .record(&venues.rebalancing, scheduler::now())
+                        {
+                            self.on_rebalance(e).await;
+                        }
+                        // what is in transit is in no venue, the balances are only
+                        // compared once every rebalancing is done
+                        if self.rebalances.is_rebalancing() {
+                            balance.clear();
+                            fetch_count = fetch_limit;
+                            continue;
+                        }
+
+                        let b = self.round(venues.total());
+                        metrics::get().set_balances(&self.env, &b);
+                        if let Some(t) = &self.timeline {
+                            if let Err(e) = t.append(&self.env, scheduler::now(), &b) {
+                                warn!(error = %e, "append balance timeline failed");
+                            }
+                        }
+                        self.venue_balances = venues;
                         if balance.is_empty() {
                             balance = b;
                             fetch_count -= 1;
@@ -70,131 +276,127 @@ impl<'a> Service<'a> {
                 }
             }
 
-            let now = chrono::Utc::now();
+            let now = scheduler::now();
+            self.valuation = self.fetch_valuation(&balance).await;
+            let value = self.valuation.total(&balance);
+            admin::get().set_balances(&self.env, &balance, value);
+            let snapshot_requested = std::mem::take(&mut self.snapshot_requested);
 
             if self.current_balance.is_empty() || self.previous_balance.is_empty() {
                 self.current_balance = balance.clone();
                 self.current_balance_update = now;
+                self.current_value = value;
                 self.previous_balance = balance;
                 self.previous_balance_update = now;
+                self.previous_value = value;
+                self.save_snapshot(SnapshotWindow::Hour);
+                self.save_snapshot(SnapshotWindow::Day);
 
                 if let Err(e) = self.send_balances_msg(&self.current_balance).await {
-                    println!("send empty balance error {}", e);
+                    warn!(error = %e, "send balances failed");
                 }
-                println!("sent empty balance");
+                info!("sent balances");
                 continue;
             }
 
+            if snapshot_requested {
+                if let Err(e) = self.send_balances_msg(&balance).await {
+                    warn!(error = %e, "send requested balances failed")
+                }
+                info!("sent requested balances")
+            }
+
             if now - self.current_balance_update >= chrono::Duration::hours(1) {
                 let diff = self.calculate_diff(&self.current_balance, &balance);
                 if !diff.is_empty() {
                     if let Err(e) = self
-                        .send_diff_msg(&self.current_balance_update, &now, &diff)
+                        .send_diff_msg(
+                            AlertKind::BalanceDiff1h,
+                            (&self.current_balance_update, self.current_value),
+                            (&now, value),
+                            &diff,
+                        )
                         .await
                     {
-                        println!("send diff 1h error {}", e);
+                        warn!(error = %e, "send 1h diff failed");
                     }
-                    println!("sent 1h diff")
+                    admin::get().set_last_diff(
+                        &self.env,
+                        AlertKind::BalanceDiff1h,
+                        self.current_balance_update,
+                        now,
+                        &diff,
+                    );
+                    info!("sent 1h diff")
                 }
                 self.current_balance = balance.clone();
                 self.current_balance_update = now;
+                self.current_value = value;
+                self.save_snapshot(SnapshotWindow::Hour);
             }
 
             if now - self.previous_balance_update >= chrono::Duration::hours(24) {
                 let diff = self.calculate_diff(&self.previous_balance, &balance);
                 if !diff.is_empty() {
                     if let Err(e) = self
-                        .send_diff_msg(&self.previous_balance_update, &now, &diff)
+                        .send_diff_msg(
+                            AlertKind::BalanceDiff24h,
+                            (&self.previous_balance_update, self.previous_value),
+                            (&now, value),
+                            &diff,
+                        )
                         .await
                     {
-                        println!("send diff 24h error {}", e);
+                        warn!(error = %e, "send 24h diff failed");
                     }
-                    println!("sent 24h diff");
+                    admin::get().set_last_diff(
+                        &self.env,
+                        AlertKind::BalanceDiff24h,
+                        self.previous_balance_update,
+                        now,
+                        &diff,
+                    );
+                    info!("sent 24h diff");
 
                     if let Err(e) = self.send_balances_msg(&balance).await {
-                        println!("send balance 24h error {}", e)
+                        warn!(error = %e, "send 24h balances failed")
                     }
-                    println!("sent 24h balance")
+                    info!("sent 24h balances")
                 }
                 self.previous_balance = balance;
                 self.previous_balance_update = now;
+                self.previous_value = value;
+                self.save_snapshot(SnapshotWindow::Day);
             }
 
-            thread::sleep(Duration::from_secs(
-                600 + (chrono::Utc::now().timestamp() % 60) as u64,
-            ));
-        }
-    }
-
-    async fn fetch_balance(&self) -> anyhow::Result<HashMap<String, f64>> {
-        let cex_balance = self.cex_dex[0].get_cex_balanace().await?;
-        if cex_balance.data.is_rebalancing {
-            return Err(anyhow::format_err!("cex balance is rebalancing"));
-        }
-
-        let dex_balance = self.fetch_dex_balance().await?;
-
-        let mut balance = HashMap::<String, f64>::new();
-
-        for (k, v) in cex_balance.data.balances {
-            match balance.get_mut(&k) {
-                None => {
-                    balance.insert(k, v.free + v.locked);
-                }
-                Some(b) => *b += v.free + v.locked,
-            }
-        }
-
-        for (k, v) in dex_balance {
-            match balance.get_mut(&k) {
-                None => {
-                    balance.insert(k, v);
-                }
-                Some(b) => *b += v,
-            }
-        }
-
-        Ok(balance)
-    }
-
-    async fn fetch_dex_balance(&self) -> anyhow::Result<HashMap<String, f64>> {
-        let mut balance = HashMap::<String, f64>::new();
-
-        for c in self.cex_dex.iter() {
-            let data = c.get_dex_balanace().await?;
-            if data.data.is_rebalancing {
-                return Err(anyhow::format_err!(
-                    "dex balance is rebalancing, {}",
-                    c.base_url()
-                ));
-            }
-            for (k, v) in data.data.contract_balances {
-                match balance.get_mut(&k) {
-                    None => {
-                        balance.insert(k, v);
-                    }
-                    Some(b) => *b += v,
-                }
-            }
-
-            for (k, v) in data.data.balances {
-                match balance.get_mut(&k) {
-                    None => {
-                        balance.insert(k, v);
+            let trigger = self.snapshot_trigger.notify();
+            tokio::select! {
+                running = scheduler::sleep(&self.check_interval, shutdown) => {
+                    if !running {
+                        info!("balance monitor stopped");
+                        return;
                     }
-                    Some(b) => *b += v,
                 }
+                _ = trigger.notified() => self.snapshot_requested = true,
             }
         }
+    }
 
-        Ok(balance)
+    fn round(&self, balances: HashMap<String, Decimal>) -> HashMap<String, Decimal> {
+        balances
+            .into_iter()
+            .map(|(k, v)| {
+                let v = self.assets.round(&k, v);
+                (k, v)
+            })
+            .collect()
     }
 
     fn calculate_diff(
         &self,
-        last_balances: &HashMap<String, f64>,
-        curr_balances: &HashMap<String, f64>,
-    ) -> Vec<(String, f64)> {
+        last_balances: &HashMap<String, Decimal>,
+        curr_balances: &HashMap<String, Decimal>,
+    ) -> Vec<(String, Decimal)> {
         let mut diff_map = curr_balances.clone();
 
         for (k, v) in last_balances.iter() {
@@ -208,20 +410,23 @@ impl<'a> Service<'a> {
 
         let mut diff_vec = diff_map
             .drain()
-            .filter(|(_, v)| v.abs() >= self.epsilon)
-            .map(|(k, v)| (k, v))
-            .collect::<Vec<(String, f64)>>();
+            .filter(|(k, v)| !v.is_zero() && v.abs() >= self.assets.get(k).diff_threshold)
+            .collect::<Vec<(String, Decimal)>>();
         diff_vec.sort_unstable_by(|a, b| a.0.cmp(&b.0));
 
         diff_vec
     }
 
+    // from and to are the window bounds with the usd value of the balances at that time
     async fn send_diff_msg(
         &self,
-        last_balance_update: &chrono::DateTime<chrono::Utc>,
-        utc_now: &chrono::DateTime<chrono::Utc>,
-        diff_vec: &[(String, f64)],
+        kind: AlertKind,
+        from: (&chrono::DateTime<chrono::Utc>, Option<Decimal>),
+        to: (&chrono::DateTime<chrono::Utc>, Option<Decimal>),
+        diff_vec: &[(String, Decimal)],
     ) -> anyhow::Result<()> {
+        let (last_balance_update, last_value) = from;
+        let (utc_now, value) = to;
         let mut msg = format!(
             "*****
 *ASSET DIFF*
@@ -233,25 +438,56 @@ impl<'a> Service<'a> {
             utc_now.to_rfc3339()
         );
 
+        let mut usd_lines = Vec::<String>::new();
         for (asset, diff) in diff_vec.iter() {
-            if *diff == 0.0 {
+            if diff.is_zero() {
                 continue;
             }
-            msg.push_str(format!("{}: {}\n", asset, diff).as_str())
+            msg.push_str(format!("{}: {}\n", asset, self.valuation.format(asset, *diff)).as_str());
+            if let Some(v) = self.valuation.usd(asset, *diff) {
+                usd_lines.push(format!("`{}`: {:+}", asset, v));
+            }
         }
 
-        match self
-            .slack
-            .send_message(String::from("alert-virtual-taker-1"), msg)
-            .await
-        {
-            Ok(()) => Ok(()),
-            Err(e) => Err(anyhow::format_err!("{}", e)),
+        // (from, to, change), a window started before the valuation is valued at the current prices
+        let total_usd = value.map(|value| {
+            let changes_usd = diff_vec
+                .iter()
+                .filter_map(|(asset, diff)| self.valuation.usd(asset, *diff))
+                .sum::<Decimal>();
+            let last_value = last_value.unwrap_or(value - changes_usd);
+            (last_value, value, value - last_value)
+        });
+        if let Some((from, to, change)) = total_usd {
+            msg.push_str(format!("TOTAL USD: {} -> {} ({:+})\n", from, to, change).as_str());
+        }
+
+        let mut b = Message::builder(msg)
+            .header("ASSET DIFF")
+            .fields(vec![
+                ("ENV", self.env.clone()),
+                ("FROM", last_balance_update.to_rfc3339()),
+                ("TO", utc_now.to_rfc3339()),
+            ])
+            .diff_rows("CHANGES", diff_vec);
+        if !usd_lines.is_empty() {
+            b = b.markdown(&format!("*CHANGES USD*\n{}", usd_lines.join("\n")));
+        }
+        if let Some((from, to, change)) = total_usd {
+            b = b.fields(vec![
+                ("TOTAL USD FROM", format!("${}", from)),
+                ("TOTAL USD TO", format!("${}", to)),
+                ("TOTAL USD CHANGE", format!("{:+}", change)),
+            ]);
         }
+        let msg = b.build();
+
+        let alert = Alert::new(kind, &self.env).with_assets(diff_vec.to_vec());
+        self.alerts.send(&alert, msg).await
     }
 
-    async fn send_balances_msg(&self, balances: &HashMap<String, f64>) -> anyhow::Result<()> {
-        let mut balances_vec: Vec<(String, f64)> =
+    async fn send_balances_msg(&self, balances: &HashMap<String, Decimal>) -> anyhow::Result<()> {
+        let mut balances_vec: Vec<(String, Decimal)> =
             balances.iter().map(|(k, v)| (k.clone(), *v)).collect();
 
         balances_vec.sort_unstable_by(|a, b| a.0.cmp(&b.0));
@@ -264,17 +500,618 @@ impl<'a> Service<'a> {
             self.env,
         );
 
-        for (asset, diff) in balances_vec.iter() {
-            msg.push_str(format!("{}: {}\n", asset, diff).as_str());
+        for (asset, amount) in balances_vec.iter() {
+            msg.push_str(
+                format!("{}: {}\n", asset, self.valuation.format(asset, *amount)).as_str(),
+            );
+        }
+        let total_usd = self.valuation.total(balances);
+        if let Some(v) = total_usd {
+            msg.push_str(format!("TOTAL USD: {}\n", v).as_str());
         }
 
-        match self
-            .slack
-            .send_message(String::from("alert-virtual-taker-1"), msg)
-            .await
-        {
-            Ok(()) => Ok(()),
-            Err(e) => Err(anyhow::format_err!("{}", e)),
+        // the breakdown only adds information with more than one venue
+        let mut breakdown = Vec::<String>::new();
+        if self.venue_balances.venues.len() > 1 {
+            for (venue, balance) in self.venue_balances.venues.iter() {
+                let mut venue_vec: Vec<(&String, &Decimal)> = balance.iter().collect();
+                venue_vec.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+                msg.push_str(format!("> {}\n", venue).as_str());
+                let mut lines = format!("*{}*\n", venue);
+                for (asset, amount) in venue_vec {
+                    let amount = self.assets.round(asset, *amount);
+                    msg.push_str(format!("{}: {}\n", asset, amount).as_str());
+                    lines.push_str(format!("`{}`: {}\n", asset, amount).as_str());
+                }
+                breakdown.push(lines);
+            }
+        }
+
+        let mut b = Message::builder(msg)
+            .header("BALANCES")
+            .fields(vec![("ENV", self.env.clone())])
+            .fields(
+                balances_vec
+                    .iter()
+                    .map(|(asset, amount)| (asset.as_str(), self.valuation.format(asset, *amount)))
+                    .collect(),
+            );
+        if let Some(v) = total_usd {
+            b = b.fields(vec![("TOTAL USD", format!("${}", v))]);
+        }
+        if !breakdown.is_empty() {
+            b = b.divider();
+        }
+        for lines in breakdown.iter() {
+            b = b.markdown(lines);
+        }
+        let msg = b.build();
+
+        let alert = Alert::new(AlertKind::BalanceSnapshot, &self.env).with_assets(balances_vec);
+        self.alerts.send(&alert, msg).await
+    }
+
+    async fn on_rebalance(&self, e: RebalanceEvent) {
+        let msg = match e {
+            RebalanceEvent::Started { venue } => {
+                info!(%venue, "rebalancing started, balances are not compared until it ends");
+                return;
+            }
+            RebalanceEvent::Finished {
+                venue,
+                duration,
+                alerted,
+            } => {
+                info!(%venue, duration_secs = duration.num_seconds(), "rebalancing finished");
+                if !alerted {
+                    return;
+                }
+                rebalance_message(
+                    &self.env,
+                    &venue,
+                    &format!("finished after {}m", duration.num_minutes()),
+                )
+            }
+            RebalanceEvent::TooLong { venue, since } => {
+                warn!(%venue, %since, "rebalancing too long");
+                rebalance_message(
+                    &self.env,
+                    &venue,
+                    &format!(
+                        "rebalancing since {} ({}m), balances are not compared until it ends",
+                        since.to_rfc3339(),
+                        (scheduler::now() - since).num_minutes()
+                    ),
+                )
+            }
+        };
+        let alert = Alert::new(AlertKind::Rebalancing, &self.env);
+        if let Err(e) = self.alerts.send(&alert, msg).await {
+            warn!(error = %e, "send rebalancing alert failed");
+        }
+    }
+
+    async fn send_error_msg(&self, msg: String) {
+        let alert = Alert::new(AlertKind::Error, &self.env);
+        if let Err(e) = self.alerts.send(&alert, msg).await {
+            warn!(error = %e, "send error alert failed");
+        }
+    }
+}
+
+fn rebalance_message(env: &str, venue: &str, status: &str) -> Message {
+    let fallback = format!(
+        "*****\n*REBALANCING*\n> ENV: {}\n> VENUE: {}\n{}\n*****",
+        env, venue, status
+    );
+    Message::builder(fallback)
+        .header("REBALANCING")
+        .fields(vec![("ENV", env.to_string()), ("VENUE", venue.to_string())])
+        .markdown(status)
+        .build()
+}
+
+// VenueClient is a cex dex url with the venues it reports
+pub struct VenueClient {
+    pub client: cexdexclient::client::CexDexClient,
+    pub cexes: Vec<String>,
+    pub dex_chains: Vec<String>,
+}
+
+// CexDexBalanceSource collects the balances of every venue of every url,
+// a cex listed by several urls is fetched from the first one only
+pub struct CexDexBalanceSource {
+    env: String,
+    cex_dex: Vec<VenueClient>,
+}
+
+impl CexDexBalanceSource {
+    pub fn new(env: String, cex_dex: Vec<VenueClient>) -> Self {
+        if cex_dex.is_empty() {
+            panic!("empty cex_dex list")
+        }
+
+        CexDexBalanceSource { env, cex_dex }
+    }
+
+    async fn fetch_all(&self) -> anyhow::Result<Balances> {
+        let mut balances = Balances::default();
+
+        let mut fetched_cexes = HashSet::<&str>::new();
+        for c in self.cex_dex.iter() {
+            for cex in c.cexes.iter() {
+                if !fetched_cexes.insert(cex.as_str()) {
+                    continue;
+                }
+                self.fetch_cex_balance(c, cex, &mut balances).await?;
+            }
+        }
+
+        for c in self.cex_dex.iter() {
+            for chain in c.dex_chains.iter() {
+                self.fetch_dex_balance(c, chain, &mut balances).await?;
+            }
+        }
+
+        // a chain rebalancing on one url has no balance, even from the other urls
+        for venue in balances.rebalancing.iter() {
+            balances.venues.remove(venue);
+        }
+
+        Ok(balances)
+    }
+
+    async fn fetch_cex_balance(
+        &self,
+        c: &VenueClient,
+        cex: &str,
+        balances: &mut Balances,
+    ) -> anyhow::Result<()> {
+        let data = match c.client.get_cex_balanace(cex).await {
+            Ok(d) => d,
+            Err(ApiError::Rebalancing { venue, .. }) => {
+                metrics::get().set_last_success(&self.env, metrics::GET_CEX_BALANCE);
+                metrics::get().inc_rebalancing_skip(&self.env, "cex");
+                balances.rebalancing.insert(venue);
+                return Ok(());
+            }
+            Err(e) => {
+                metrics::get().inc_fetch_error(&self.env, metrics::GET_CEX_BALANCE, e.kind());
+                return Err(e.into());
+            }
+        };
+        metrics::get().set_last_success(&self.env, metrics::GET_CEX_BALANCE);
+
+        let venue = format!("cex/{}", cex);
+        for (k, v) in data.data.balances {
+            balances.add(&venue, k, v.free + v.locked);
+        }
+
+        Ok(())
+    }
+
+    async fn fetch_dex_balance(
+        &self,
+        c: &VenueClient,
+        chain: &str,
+        balances: &mut Balances,
+    ) -> anyhow::Result<()> {
+        let data = match c.client.get_dex_balanace(chain).await {
+            Ok(d) => d,
+            Err(ApiError::Rebalancing { venue, .. }) => {
+                metrics::get().set_last_success(&self.env, metrics::GET_DEX_BALANCE);
+                metrics::get().inc_rebalancing_skip(&self.env, "dex");
+                balances.rebalancing.insert(venue);
+                return Ok(());
+            }
+            Err(e) => {
+                metrics::get().inc_fetch_error(&self.env, metrics::GET_DEX_BALANCE, e.kind());
+                return Err(e.into());
+            }
+        };
+        metrics::get().set_last_success(&self.env, metrics::GET_DEX_BALANCE);
+
+        // the wallets of several urls on the same chain add up to one venue
+        let venue = format!("dex/{}", chain);
+        for (k, v) in data.data.contract_balances {
+            balances.add(&venue, k, v);
+        }
+        for (k, v) in data.data.balances {
+            balances.add(&venue, k, v);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BalanceSource for CexDexBalanceSource {
+    async fn fetch_balance(&self) -> anyhow::Result<Balances> {
+        self.fetch_all().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeSet, HashMap},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use cex_dex_monitor::{
+        AlertKind, AlertRoutingConfig, AssetConfig, AssetsConfig, CexDexClientConfig,
+        IntervalConfig, SchedulerConfig,
+    };
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use tokio_util::sync::CancellationToken;
+
+    use super::{BalanceSource, Balances, CexDexBalanceSource, Service, VenueClient};
+    use crate::{
+        cexdexclient::client::CexDexClient,
+        notifier::Notifier,
+        pricing::fixed::FixedPriceProvider,
+        routing::Router,
+        slackclient::message::Message,
+        store::memory::MemoryStore,
+        testutil::{ok, MockServer},
+    };
+
+    #[derive(Clone, Default)]
+    struct FakeSource(Arc<Mutex<HashMap<String, Decimal>>>);
+
+    #[async_trait]
+    impl BalanceSource for FakeSource {
+        async fn fetch_balance(&self) -> anyhow::Result<Balances> {
+            let mut b = Balances::default();
+            for (asset, amount) in self.0.lock().unwrap().iter() {
+                b.add("cex/binance", asset.clone(), *amount);
+            }
+            Ok(b)
+        }
+    }
+
+    // FakeSource with venues rebalancing
+    #[derive(Clone, Default)]
+    struct RebalancingSource {
+        balances: FakeSource,
+        rebalancing: Arc<Mutex<BTreeSet<String>>>,
+    }
+
+    #[async_trait]
+    impl BalanceSource for RebalancingSource {
+        async fn fetch_balance(&self) -> anyhow::Result<Balances> {
+            let mut b = self.balances.fetch_balance().await?;
+            b.rebalancing = self.rebalancing.lock().unwrap().clone();
+            Ok(b)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct RecordingSink(Arc<Mutex<Vec<Message>>>);
+
+    impl RecordingSink {
+        fn headers(&self) -> Vec<String> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|m| m.text.lines().nth(1).unwrap_or_default().to_string())
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl Notifier for RecordingSink {
+        fn has_channel(&self, _: &str) -> bool {
+            true
         }
+
+        async fn send(&self, _: &str, _: AlertKind, msg: &Message) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(msg.clone());
+            Ok(())
+        }
+    }
+
+    // fetch every 20s, check every 600s: checks run at t = 20 (seed), 40, 660, 1280, ...
+    #[tokio::test(start_paused = true)]
+    async fn diffs_follow_1h_and_24h_cadence() {
+        let t0 = tokio::time::Instant::now();
+        let sleep_until = |secs| tokio::time::sleep_until(t0 + Duration::from_secs(secs));
+        let source = FakeSource::default();
+        source
+            .0
+            .lock()
+            .unwrap()
+            .insert("USDT".to_string(), dec!(100));
+        let sink = RecordingSink::default();
+        let router = Router::new(&AlertRoutingConfig::default(), Arc::new(sink.clone()));
+        let schedule = SchedulerConfig {
+            balance_fetch: IntervalConfig {
+                period_secs: 20,
+                jitter_secs: 0,
+            },
+            balance_check: IntervalConfig {
+                period_secs: 600,
+                jitter_secs: 0,
+            },
+            ..Default::default()
+        };
+        let shutdown = CancellationToken::new();
+
+        let mut s = Service::new(
+            "prod".to_string(),
+            &AssetsConfig::default(),
+            Box::new(source.clone()),
+            &router,
+            Arc::new(MemoryStore::new()),
+            &schedule,
+            &CexDexClientConfig::default(),
+        )
+        .with_prices(Arc::new(FixedPriceProvider::new(HashMap::from([(
+            "USDT".to_string(),
+            dec!(1),
+        )]))));
+
+        let driver = async {
+            sleep_until(30).await;
+            assert_eq!(sink.headers(), vec!["*BALANCES*"]);
+            source
+                .0
+                .lock()
+                .unwrap()
+                .insert("USDT".to_string(), dec!(150));
+
+            // first check at least 1h after the seed is at t = 3760
+            sleep_until(3700).await;
+            assert_eq!(sink.headers().len(), 1);
+            sleep_until(3800).await;
+            assert_eq!(sink.headers(), vec!["*BALANCES*", "*ASSET DIFF*"]);
+            let diff = sink.0.lock().unwrap()[1].text.clone();
+            assert!(diff.contains("USDT: 50 ($50)\nTOTAL USD: 100 -> 150 (+50)\n"));
+
+            // first check at least 24h after the seed is at t = 86840
+            sleep_until(86800).await;
+            assert_eq!(sink.headers().len(), 2);
+            sleep_until(86900).await;
+            assert_eq!(
+                sink.headers(),
+                vec!["*BALANCES*", "*ASSET DIFF*", "*ASSET DIFF*", "*BALANCES*"]
+            );
+
+            shutdown.cancel();
+        };
+
+        tokio::join!(s.monitor_balance(&shutdown), driver);
+    }
+
+    // fetch every 20s, check every 600s: the seed at t = 20 is checked again at t = 40
+    #[tokio::test(start_paused = true)]
+    async fn rebalancing_is_alerted_and_not_diffed() {
+        let t0 = tokio::time::Instant::now();
+        let sleep_until = |secs| tokio::time::sleep_until(t0 + Duration::from_secs(secs));
+        let source = RebalancingSource::default();
+        let set_usdt = |amount| {
+            source
+                .balances
+                .0
+                .lock()
+                .unwrap()
+                .insert("USDT".to_string(), amount)
+        };
+        set_usdt(dec!(100));
+        let sink = RecordingSink::default();
+        let router = Router::new(&AlertRoutingConfig::default(), Arc::new(sink.clone()));
+        let schedule = SchedulerConfig {
+            balance_fetch: IntervalConfig {
+                period_secs: 20,
+                jitter_secs: 0,
+            },
+            balance_check: IntervalConfig {
+                period_secs: 600,
+                jitter_secs: 0,
+            },
+            ..Default::default()
+        };
+        let shutdown = CancellationToken::new();
+
+        let mut s = Service::new(
+            "prod".to_string(),
+            &AssetsConfig::default(),
+            Box::new(source.clone()),
+            &router,
+            Arc::new(MemoryStore::new()),
+            &schedule,
+            &CexDexClientConfig {
+                rebalancing_alert_after_secs: 300,
+                ..Default::default()
+            },
+        );
+
+        let driver = async {
+            sleep_until(30).await;
+            assert_eq!(sink.headers(), vec!["*BALANCES*"]);
+            // half of the usdt is in transit
+            set_usdt(dec!(50));
+            source
+                .rebalancing
+                .lock()
+                .unwrap()
+                .insert("cex/binance".to_string());
+
+            // started at t = 40, too long from t = 340
+            sleep_until(330).await;
+            assert_eq!(sink.headers().len(), 1);
+            sleep_until(350).await;
+            assert_eq!(sink.headers(), vec!["*BALANCES*", "*REBALANCING*"]);
+            sleep_until(1190).await;
+            assert_eq!(sink.headers().len(), 2);
+
+            set_usdt(dec!(100));
+            source.rebalancing.lock().unwrap().clear();
+            sleep_until(1210).await;
+            assert_eq!(
+                sink.headers(),
+                vec!["*BALANCES*", "*REBALANCING*", "*REBALANCING*"]
+            );
+            assert!(sink.0.lock().unwrap()[2]
+                .text
+                .contains("finished after 19m"));
+
+            // the 1h check compares settled balances only
+            sleep_until(3900).await;
+            assert_eq!(sink.headers().len(), 3);
+
+            shutdown.cancel();
+        };
+
+        tokio::join!(s.monitor_balance(&shutdown), driver);
+    }
+
+    #[test]
+    fn diff_uses_per_asset_threshold() {
+        let mut assets = AssetsConfig::default();
+        assets.per_asset.insert(
+            "BTC".to_string(),
+            AssetConfig {
+                precision: 8,
+                diff_threshold: dec!(0.0001),
+            },
+        );
+        assets.per_asset.insert(
+            "SHIB".to_string(),
+            AssetConfig {
+                precision: 0,
+                diff_threshold: dec!(1000),
+            },
+        );
+        let router = Router::new(
+            &AlertRoutingConfig::default(),
+            Arc::new(RecordingSink::default()),
+        );
+        let s = Service::new(
+            "prod".to_string(),
+            &assets,
+            Box::new(FakeSource::default()),
+            &router,
+            Arc::new(MemoryStore::new()),
+            &SchedulerConfig::default(),
+            &CexDexClientConfig::default(),
+        );
+
+        let before = HashMap::from([
+            ("BTC".to_string(), dec!(1)),
+            ("SHIB".to_string(), dec!(5000000)),
+            ("USDT".to_string(), dec!(0.1)),
+        ]);
+        let after = HashMap::from([
+            ("BTC".to_string(), dec!(1.00002)),
+            ("SHIB".to_string(), dec!(5000999)),
+            ("USDT".to_string(), dec!(0.30001)),
+        ]);
+        assert_eq!(
+            s.calculate_diff(&before, &after),
+            vec![("USDT".to_string(), dec!(0.20001))]
+        );
+
+        // rounding to the precision drops the noise below it
+        let rounded = s.round(HashMap::from([("SHIB".to_string(), dec!(5000999.6))]));
+        assert_eq!(rounded["SHIB"], dec!(5001000));
+    }
+
+    fn cex_balance(usdt: f64) -> String {
+        format!(
+            r#"{{"result": {{"code": 0, "message": "ok"}},
+            "data": {{"id": "1", "is_rebalancing": false, "balances": {{"USDT": {{"free": {}, "locked": 0.2}}}}}}}}"#,
+            usdt
+        )
+    }
+
+    fn dex_balance(knc: f64) -> String {
+        format!(
+            r#"{{"result": {{"code": 0, "message": "ok"}},
+            "data": {{"id": "1", "is_rebalancing": false, "balances": {{"KNC": {}}}, "contract_balances": {{"USDT": 0.7}}}}}}"#,
+            knc
+        )
+    }
+
+    fn venue_client(server: &MockServer, cexes: &[&str], dex_chains: &[&str]) -> VenueClient {
+        VenueClient {
+            client: CexDexClient::new(
+                server.url.clone(),
+                "user".to_string(),
+                "pass".to_string(),
+                &CexDexClientConfig::default(),
+            ),
+            cexes: cexes.iter().map(|c| c.to_string()).collect(),
+            dex_chains: dex_chains.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn balances_are_aggregated_per_venue() {
+        let first = MockServer::start().await;
+        first.respond("/cex/binance/balances", vec![ok(&cex_balance(10.1))]);
+        first.respond("/cex/okx/balances", vec![ok(&cex_balance(20.0))]);
+        first.respond("/dex/polygon/balances", vec![ok(&dex_balance(0.1))]);
+        let second = MockServer::start().await;
+        second.respond("/dex/polygon/balances", vec![ok(&dex_balance(0.2))]);
+        second.respond("/dex/bsc/balances", vec![ok(&dex_balance(3.0))]);
+
+        let source = CexDexBalanceSource::new(
+            "prod".to_string(),
+            vec![
+                venue_client(&first, &["binance", "okx"], &["polygon"]),
+                venue_client(&second, &["binance"], &["polygon", "bsc"]),
+            ],
+        );
+        let b = source.fetch_balance().await.unwrap();
+
+        // the shared binance account is only read from the first url
+        assert_eq!(second.hits("/cex/binance/balances"), 0);
+        assert_eq!(
+            b.venues.keys().collect::<Vec<&String>>(),
+            vec!["cex/binance", "cex/okx", "dex/bsc", "dex/polygon"]
+        );
+        // amounts add up exactly, without float noise
+        assert_eq!(b.venues["cex/binance"]["USDT"], dec!(10.3));
+        assert_eq!(b.venues["dex/polygon"]["KNC"], dec!(0.3));
+        assert_eq!(b.venues["dex/polygon"]["USDT"], dec!(1.4));
+        let total = b.total();
+        assert_eq!(total["USDT"], dec!(10.3) + dec!(20.2) + dec!(2.1));
+        assert_eq!(total["KNC"], dec!(3.3));
+    }
+
+    #[tokio::test]
+    async fn rebalancing_venue_has_no_balance() {
+        let first = MockServer::start().await;
+        first.respond("/cex/binance/balances", vec![ok(&cex_balance(10.0))]);
+        first.respond("/dex/polygon/balances", vec![ok(&dex_balance(0.1))]);
+        let second = MockServer::start().await;
+        second.respond(
+            "/dex/polygon/balances",
+            vec![ok(&dex_balance(0.2).replace(
+                r#""is_rebalancing": false"#,
+                r#""is_rebalancing": true"#,
+            ))],
+        );
+
+        let source = CexDexBalanceSource::new(
+            "prod".to_string(),
+            vec![
+                venue_client(&first, &["binance"], &["polygon"]),
+                venue_client(&second, &[], &["polygon"]),
+            ],
+        );
+        let b = source.fetch_balance().await.unwrap();
+
+        assert_eq!(
+            b.venues.keys().collect::<Vec<&String>>(),
+            vec!["cex/binance"]
+        );
+        assert_eq!(b.rebalancing, BTreeSet::from(["dex/polygon".to_string()]));
     }
 }
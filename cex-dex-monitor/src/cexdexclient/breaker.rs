@@ -0,0 +1,107 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+// CircuitBreaker stops calling a base url for open_duration after failure_threshold
+// consecutive failures, then lets a single trial request through. A trial without
+// result after open_duration (its future was dropped) is replaced by a new one
+pub struct CircuitBreaker {
+    settings: Mutex<Settings>,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Clone, Copy)]
+struct Settings {
+    failure_threshold: u32,
+    open_duration: Duration,
+}
+
+impl Settings {
+    fn new(failure_threshold: u32, open_duration: Duration) -> Settings {
+        Settings {
+            failure_threshold: failure_threshold.max(1),
+            open_duration,
+        }
+    }
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    trial_started_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            settings: Mutex::new(Settings::new(failure_threshold, open_duration)),
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    // new thresholds, from a reloaded config, keeping the state
+    pub fn configure(&self, failure_threshold: u32, open_duration: Duration) {
+        *self.settings.lock().unwrap() = Settings::new(failure_threshold, open_duration);
+    }
+
+    // false when the request must not be sent
+    pub fn allow(&self) -> bool {
+        let open_duration = self.settings.lock().unwrap().open_duration;
+        let mut s = self.state.lock().unwrap();
+        match s.opened_at {
+            None => true,
+            Some(at) => {
+                if at.elapsed() < open_duration {
+                    return false;
+                }
+                if s.trial_started_at
+                    .is_some_and(|t| t.elapsed() < open_duration)
+                {
+                    return false;
+                }
+                s.trial_started_at = Some(Instant::now());
+                true
+            }
+        }
+    }
+
+    pub fn on_success(&self) {
+        let mut s = self.state.lock().unwrap();
+        *s = BreakerState::default();
+    }
+
+    pub fn on_failure(&self) {
+        let failure_threshold = self.settings.lock().unwrap().failure_threshold;
+        let mut s = self.state.lock().unwrap();
+        s.consecutive_failures += 1;
+        if s.trial_started_at.is_some() || s.consecutive_failures >= failure_threshold {
+            s.opened_at = Some(Instant::now());
+            s.trial_started_at = None;
+        }
+    }
+}
+
+// one breaker per base url, shared by every client of that url, with the
+// thresholds of the last client created
+pub fn for_base_url(
+    base_url: &str,
+    failure_threshold: u32,
+    open_duration: Duration,
+) -> Arc<CircuitBreaker> {
+    static BREAKERS: OnceLock<Mutex<HashMap<String, Arc<CircuitBreaker>>>> = OnceLock::new();
+
+    let breaker = BREAKERS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap()
+        .entry(base_url.to_string())
+        .or_insert_with(|| Arc::new(CircuitBreaker::new(failure_threshold, open_duration)))
+        .clone();
+    breaker.configure(failure_threshold, open_duration);
+    breaker
+}
@@ -1,28 +1,107 @@
 #![allow(dead_code)]
 
-use super::dto::*;
+use super::{
+    breaker,
+    breaker::CircuitBreaker,
+    dto::*,
+    error::{self, ApiError},
+    recorder::{self, Record, Recorder, Replay},
+};
+use crate::scheduler;
+use cex_dex_monitor::{redact_url, CexDexClientConfig};
+use rand::Rng;
 use reqwest::header::CONTENT_TYPE;
-use std::error::Error;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{sync::Arc, time::Duration};
+use tracing::warn;
 
 pub struct CexDexClient {
     client: reqwest::Client,
     base_url: String,
     user: String,
     pass: String,
+    // base_url without credentials, for logs and errors
+    log_url: String,
+    policy: RetryPolicy,
+    breaker: Arc<CircuitBreaker>,
+    recorder: Option<Arc<Recorder>>,
+    replay: Option<Arc<Replay>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub timeout: Duration,
+    pub max_retries: u32,
+    pub backoff_base: Duration,
+    pub backoff_max: Duration,
+}
+
+impl RetryPolicy {
+    // exponential backoff with full jitter
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .backoff_base
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.backoff_max);
+        let ms = exp.as_millis() as u64;
+        if ms == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(rand::thread_rng().gen_range(0..=ms))
+    }
+}
+
+impl From<&CexDexClientConfig> for RetryPolicy {
+    fn from(cfg: &CexDexClientConfig) -> Self {
+        RetryPolicy {
+            timeout: Duration::from_millis(cfg.timeout_ms),
+            max_retries: cfg.max_retries,
+            backoff_base: Duration::from_millis(cfg.backoff_base_ms),
+            backoff_max: Duration::from_millis(cfg.backoff_max_ms),
+        }
+    }
+}
+
+// the result of every response, decoded alone to report the api errors of
+// responses without data
+#[derive(Deserialize)]
+struct Envelope {
+    result: ResponseResult,
 }
 
 impl CexDexClient {
     // https://github.com/hyperium/hyper/issues/2136
-    pub fn new(base_url: String, user: String, pass: String) -> CexDexClient {
+    pub fn new(
+        base_url: String,
+        user: String,
+        pass: String,
+        cfg: &CexDexClientConfig,
+    ) -> CexDexClient {
+        let recorder = match cfg.record_path.as_str() {
+            "" => None,
+            path => recorder::for_path(path)
+                .map_err(|e| warn!(%path, error = %e, "open recording failed"))
+                .ok(),
+        };
+
         CexDexClient {
             client: reqwest::Client::builder()
                 .pool_max_idle_per_host(0)
                 .pool_idle_timeout(None)
                 .build()
                 .unwrap(),
+            breaker: breaker::for_base_url(
+                &base_url,
+                cfg.breaker_failure_threshold,
+                Duration::from_secs(cfg.breaker_open_secs),
+            ),
+            log_url: redact_url(&base_url),
             base_url,
             user,
             pass,
+            policy: RetryPolicy::from(cfg),
+            recorder,
+            replay: recorder::replay(),
         }
     }
 
@@ -30,95 +109,203 @@ impl CexDexClient {
         self.base_url.clone()
     }
 
-    pub async fn get_filled_done_states(&self) -> Result<Response, Box<dyn Error + Send + Sync>> {
-        let body = self
-            .client
-            .get(format!("{}/state", self.base_url))
-            .query(&[("is_done", "true"), ("fill_state", "true"), ("size", "20")])
-            .header(CONTENT_TYPE, "application/json")
-            .basic_auth(&self.user, Some(&self.pass))
-            .send()
-            .await?
-            .text()
-            .await?;
+    pub async fn get_filled_done_states(&self) -> Result<Response, ApiError> {
+        self.get_filled_states(true, 20).await
+    }
 
-        let resp: Response = match serde_json::from_str(&body) {
-            Ok(r) => r,
-            Err(e) => {
-                println!("serde_json error {}, body {}", e, &body);
-                return Err(e.into());
-            }
-        };
+    // the last size states with their orders and txs
+    pub async fn get_filled_states(
+        &self,
+        is_done: bool,
+        size: usize,
+    ) -> Result<Response, ApiError> {
+        self.get(
+            "/state",
+            &[
+                ("is_done", if is_done { "true" } else { "false" }),
+                ("fill_state", "true"),
+                ("size", &size.to_string()),
+            ],
+        )
+        .await
+    }
 
+    pub async fn get_cex_balanace(&self, cex: &str) -> Result<GetCEXBalanceResponse, ApiError> {
+        let path = format!("/cex/{}/balances", cex);
+        let resp: GetCEXBalanceResponse = self.get(&path, &[]).await?;
+        if resp.data.is_rebalancing {
+            return Err(ApiError::Rebalancing {
+                url: self.url(&path),
+                venue: format!("cex/{}", cex),
+            });
+        }
         Ok(resp)
     }
 
-    pub async fn get_running_states(&self) -> Result<Response, Box<dyn Error + Send + Sync>> {
-        let body = self
-            .client
-            .get(format!("{}/state", self.base_url))
-            .header(CONTENT_TYPE, "application/json")
-            .basic_auth(&self.user, Some(&self.pass))
-            .send()
-            .await?
-            .text()
-            .await?;
+    pub async fn get_dex_balanace(&self, chain: &str) -> Result<GetDEXBalanceResponse, ApiError> {
+        let path = format!("/dex/{}/balances", chain);
+        let resp: GetDEXBalanceResponse = self.get(&path, &[]).await?;
+        if resp.data.is_rebalancing {
+            return Err(ApiError::Rebalancing {
+                url: self.url(&path),
+                venue: format!("dex/{}", chain),
+            });
+        }
+        Ok(resp)
+    }
 
-        let resp = match serde_json::from_str(&body) {
-            Ok(r) => r,
-            Err(e) => {
-                println!("serde_json error {}, body {}", e, &body);
-                return Err(e.into());
+    // for logs and errors
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.log_url, path)
+    }
+
+    // GET with retries on transient errors, through the breaker of the base url
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, ApiError> {
+        let mut attempt = 0;
+        let mut last_error = None;
+        loop {
+            if !self.breaker.allow() {
+                // a retry stopped by the open circuit fails with the upstream error
+                return Err(last_error.unwrap_or_else(|| ApiError::CircuitOpen {
+                    url: self.url(path),
+                }));
             }
-        };
 
-        Ok(resp)
+            let e = match self.get_once(path, query).await {
+                Ok(resp) => {
+                    self.breaker.on_success();
+                    return Ok(resp);
+                }
+                Err(e) => e,
+            };
+            if !e.is_transient() {
+                // the server answered, it is not down
+                self.breaker.on_success();
+                return Err(e);
+            }
+
+            self.breaker.on_failure();
+            if attempt >= self.policy.max_retries {
+                return Err(e);
+            }
+            let backoff = self.policy.backoff(attempt);
+            warn!(
+                url = %self.url(path),
+                error = %e,
+                ?backoff,
+                "request failed, retrying"
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+            last_error = Some(e);
+        }
     }
 
-    pub async fn get_cex_balanace(&self) -> anyhow::Result<GetCEXBalanceResponse> {
-        let body = self
-            .client
-            .get(format!("{}/cex/binance/balances", self.base_url))
-            .header(CONTENT_TYPE, "application/json")
-            .basic_auth(&self.user, Some(&self.pass))
-            .send()
-            .await?
-            .text()
-            .await?;
+    async fn get_once<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, ApiError> {
+        let (status, body) = match &self.replay {
+            Some(replay) => self.replayed(replay, path, query)?,
+            None => self.fetch(path, query).await?,
+        };
+
+        if !status.is_success() {
+            return Err(ApiError::status(self.url(path), status.as_u16(), &body));
+        }
 
-        let resp = match serde_json::from_str(&body) {
-            Ok(r) => r,
-            Err(e) => {
-                println!("serde_json error {}, body {}", e, &body);
-                return Err(e.into());
+        if let Ok(Envelope { result }) = serde_json::from_str::<Envelope>(&body) {
+            if result.code != 0 {
+                return Err(ApiError::Api {
+                    url: self.url(path),
+                    code: result.code,
+                    message: result.message,
+                });
             }
-        };
+        }
 
-        Ok(resp)
+        serde_json::from_str(&body).map_err(|e| {
+            warn!(
+                url = %self.url(path),
+                error = %e,
+                body = %error::truncate(&body, error::MAX_BODY_CHARS),
+                "decode response failed"
+            );
+            ApiError::decode(self.url(path), &e, &body)
+        })
     }
+}
 
-    pub async fn get_dex_balanace(&self) -> anyhow::Result<GetDEXBalanceResponse> {
-        let body = self
+impl CexDexClient {
+    async fn fetch(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<(reqwest::StatusCode, String), ApiError> {
+        let resp = self
             .client
-            .get(format!("{}/dex/polygon/balances", self.base_url))
+            .get(format!("{}{}", self.base_url, path))
+            .query(query)
             .header(CONTENT_TYPE, "application/json")
             .basic_auth(&self.user, Some(&self.pass))
+            .timeout(self.policy.timeout)
             .send()
-            .await?
+            .await
+            .map_err(|e| self.transport_error(path, e))?;
+
+        let status = resp.status();
+        let body = resp
             .text()
-            .await?;
-
-        let resp = match serde_json::from_str(&body) {
-            Ok(r) => r,
-            Err(e) => {
-                println!(
-                    "serde_json error [{}], body [{}], url [{}]",
-                    e, &body, self.base_url
-                );
-                return Err(e.into());
+            .await
+            .map_err(|e| self.transport_error(path, e))?;
+
+        if let Some(r) = &self.recorder {
+            let record = Record {
+                at: scheduler::now(),
+                base_url: self.log_url.clone(),
+                path: path.to_string(),
+                query: recorder::query_string(query),
+                status: status.as_u16(),
+                body: body.clone(),
+            };
+            if let Err(e) = r.record(&record) {
+                warn!(url = %self.url(path), error = %e, "record response failed");
             }
-        };
+        }
 
-        Ok(resp)
+        Ok((status, body))
+    }
+
+    // a missing response is a transient error, like an unreachable api
+    fn replayed(
+        &self,
+        replay: &Replay,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<(reqwest::StatusCode, String), ApiError> {
+        let query = recorder::query_string(query);
+        let r = replay
+            .response(&self.log_url, path, &query, scheduler::now())
+            .ok_or_else(|| ApiError::Transport {
+                url: format!("{}?{}", self.url(path), query),
+                error: "no recorded response".to_string(),
+            })?;
+        let status = reqwest::StatusCode::from_u16(r.status)
+            .map_err(|_| ApiError::status(self.url(path), r.status, &r.body))?;
+
+        Ok((status, r.body.clone()))
+    }
+
+    // reqwest errors carry the url with its credentials
+    fn transport_error(&self, path: &str, e: reqwest::Error) -> ApiError {
+        ApiError::Transport {
+            url: self.url(path),
+            error: e.without_url().to_string(),
+        }
     }
 }
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use rust_decimal::{prelude::ToPrimitive, Decimal};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -22,7 +23,7 @@ pub struct StateData {
     pub dex_chain: String,
     pub dex_wallet: String,
     pub token: String,
-    pub base_amount: f64,
+    pub base_amount: Decimal,
     pub side: String,
     pub p1_price_diff: f64,
     pub p1_profitable_threshold: f64,
@@ -35,23 +36,39 @@ pub struct StateData {
     pub p1_cex_orders: Option<Vec<CexOrderData>>,
     pub p2_cex_orders: Option<Vec<CexOrderData>>,
     pub p2_dex_txs: Option<Vec<DexTxData>>,
-    pub asset_change: Option<HashMap<String, f64>>,
-    pub asset_change_with_fee: Option<HashMap<String, f64>>,
+    pub asset_change: Option<HashMap<String, Decimal>>,
+    pub asset_change_with_fee: Option<HashMap<String, Decimal>>,
 }
 
 impl StateData {
-    fn sum_filled(&self, v: &[CexOrderData], f: fn(&CexOrderData) -> f64) -> f64 {
+    // created_time is either rfc3339 or unix millis
+    pub fn created_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        if let Ok(t) = chrono::DateTime::parse_from_rfc3339(&self.created_time) {
+            return Some(t.with_timezone(&chrono::Utc));
+        }
+        self.created_time
+            .parse::<i64>()
+            .ok()
+            .and_then(chrono::DateTime::from_timestamp_millis)
+    }
+
+    fn sum_filled(&self, v: &[CexOrderData], f: fn(&CexOrderData) -> Decimal) -> Decimal {
         v.iter().map(f).sum()
     }
 
     pub fn get_cex_price(&self, o: &Option<Vec<CexOrderData>>) -> f64 {
+        self.cex_price(o).to_f64().unwrap_or_default()
+    }
+
+    // filled quote per filled base, zero without fills
+    pub fn cex_price(&self, o: &Option<Vec<CexOrderData>>) -> Decimal {
         match o {
-            None => 0.0,
+            None => Decimal::ZERO,
             Some(v) => {
                 let base_filled = self.sum_filled(v, |x| x.filled_base_amount);
                 let quote_filled = self.sum_filled(v, |x| x.filled_quote_amount);
-                if base_filled == 0.0 {
-                    return 0.0;
+                if base_filled.is_zero() {
+                    return Decimal::ZERO;
                 }
                 quote_filled / base_filled
             }
@@ -66,16 +83,16 @@ impl StateData {
         }
     }
 
-    pub fn p1_sum_base_filled(&self) -> f64 {
+    pub fn p1_sum_base_filled(&self) -> Decimal {
         match &self.p1_cex_orders {
-            None => 0.0,
+            None => Decimal::ZERO,
             Some(v) => self.sum_filled(v, |x| x.filled_base_amount),
         }
     }
 
-    pub fn p1_sum_quote_filled(&self) -> f64 {
+    pub fn p1_sum_quote_filled(&self) -> Decimal {
         match &self.p1_cex_orders {
-            None => 0.0,
+            None => Decimal::ZERO,
             Some(v) => self.sum_filled(v, |x| x.filled_quote_amount),
         }
     }
@@ -88,16 +105,16 @@ impl StateData {
         }
     }
 
-    pub fn p2_sum_base_filled(&self) -> f64 {
+    pub fn p2_sum_base_filled(&self) -> Decimal {
         match &self.p2_cex_orders {
-            None => 0.0,
+            None => Decimal::ZERO,
             Some(v) => self.sum_filled(v, |x| x.filled_base_amount),
         }
     }
 
-    pub fn p2_sum_quote_filled(&self) -> f64 {
+    pub fn p2_sum_quote_filled(&self) -> Decimal {
         match &self.p2_cex_orders {
-            None => 0.0,
+            None => Decimal::ZERO,
             Some(v) => self.sum_filled(v, |x| x.filled_quote_amount),
         }
     }
@@ -110,14 +127,14 @@ impl StateData {
         }
     }
 
-    pub fn p2_sum_token_filled(&self, token: &String) -> f64 {
+    pub fn p2_sum_token_filled(&self, token: &String) -> Decimal {
         match &self.p2_dex_txs {
-            None => 0.0,
+            None => Decimal::ZERO,
             Some(v) => v
                 .iter()
                 .map(|x| {
                     if !x.status.eq("EXECUTED") {
-                        return 0.0;
+                        return Decimal::ZERO;
                     }
                     if x.token_in.eq(token) {
                         x.amount_in
@@ -140,6 +157,17 @@ impl StateData {
         summary
     }
 
+    // (tx hash, status) of the p2 dex txs
+    pub fn p2_txs_vec(&self) -> Vec<(String, String)> {
+        match &self.p2_dex_txs {
+            None => Vec::new(),
+            Some(txs) => txs
+                .iter()
+                .map(|tx| (tx.tx_hash.clone(), tx.status.clone()))
+                .collect(),
+        }
+    }
+
     pub fn asset_changes(&self) -> String {
         let mut asset_changes = String::from("");
         if let Some(m) = &self.asset_change_with_fee {
@@ -149,6 +177,13 @@ impl StateData {
         }
         asset_changes
     }
+
+    pub fn asset_changes_vec(&self) -> Vec<(String, Decimal)> {
+        match &self.asset_change_with_fee {
+            None => Vec::new(),
+            Some(m) => m.iter().map(|(k, v)| (k.clone(), *v)).collect(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -160,15 +195,22 @@ pub struct CexOrderData {
     pub side: String,
     pub actual_price: f64,
     pub actual_price_with_fee: f64,
-    pub base_amount: f64,
-    pub filled_base_amount: f64,
-    pub filled_quote_amount: f64,
+    pub base_amount: Decimal,
+    pub filled_base_amount: Decimal,
+    pub filled_quote_amount: Decimal,
     pub fee_asset: String,
-    pub fee_amount: f64,
+    pub fee_amount: Decimal,
     pub filled_at: i64,
     pub created_time: i64,
 }
 
+impl CexOrderData {
+    // created_time and filled_at are unix millis, 0 when not yet
+    pub fn fill_latency_ms(&self) -> Option<i64> {
+        latency_ms(self.created_time, self.filled_at)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DexTxData {
     pub nonce: i64,
@@ -178,9 +220,9 @@ pub struct DexTxData {
     pub router_address: String,
     pub token_in: String,
     pub token_out: String,
-    pub amount_in: f64,
-    pub estimated_amount_out: f64,
-    pub actual_amount_out: f64,
+    pub amount_in: Decimal,
+    pub estimated_amount_out: Decimal,
+    pub actual_amount_out: Decimal,
     pub estimated_price: f64,
     pub estimated_price_with_fee: f64,
     pub actual_price: f64,
@@ -195,6 +237,24 @@ pub struct DexTxData {
     pub native_token_price_in_quote: f64,
 }
 
+impl DexTxData {
+    // estimated_at, broadcasted_at and mined_at are unix millis, 0 when not yet
+    pub fn estimate_to_broadcast_ms(&self) -> Option<i64> {
+        latency_ms(self.estimated_at, self.broadcasted_at)
+    }
+
+    pub fn broadcast_to_mine_ms(&self) -> Option<i64> {
+        latency_ms(self.broadcasted_at, self.mined_at)
+    }
+}
+
+fn latency_ms(from: i64, to: i64) -> Option<i64> {
+    if from == 0 || to == 0 {
+        return None;
+    }
+    Some(to - from)
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GetCEXBalanceResponse {
     pub result: ResponseResult,
@@ -210,8 +270,8 @@ pub struct CEXBalanceData {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CEXBalance {
-    pub free: f64,
-    pub locked: f64,
+    pub free: Decimal,
+    pub locked: Decimal,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -224,6 +284,6 @@ pub struct GetDEXBalanceResponse {
 pub struct DEXBalanceData {
     pub id: String,
     pub is_rebalancing: bool,
-    pub balances: HashMap<String, f64>,
-    pub contract_balances: HashMap<String, f64>,
+    pub balances: HashMap<String, Decimal>,
+    pub contract_balances: HashMap<String, Decimal>,
 }
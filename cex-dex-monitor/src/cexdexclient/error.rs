@@ -0,0 +1,140 @@
+use std::fmt;
+
+// bodies in errors are cut to this many chars
+pub const MAX_BODY_CHARS: usize = 256;
+
+// ApiError is why a request to the cex dex api did not return usable data,
+// url is the base url without credentials followed by the path
+#[derive(Debug)]
+pub enum ApiError {
+    // no response: connection error, timeout or no recorded response in a replay
+    Transport {
+        url: String,
+        error: String,
+    },
+    // the breaker of the base url is open, nothing was sent
+    CircuitOpen {
+        url: String,
+    },
+    Status {
+        url: String,
+        status: u16,
+        body: String,
+    },
+    // the body is not the expected json
+    Decode {
+        url: String,
+        error: String,
+        body: String,
+    },
+    // result.code is not 0
+    Api {
+        url: String,
+        code: i64,
+        message: String,
+    },
+    // the balances of the venue are moving between cex and dex
+    Rebalancing {
+        url: String,
+        venue: String,
+    },
+}
+
+impl ApiError {
+    pub fn status(url: String, status: u16, body: &str) -> ApiError {
+        ApiError::Status {
+            url,
+            status,
+            body: truncate(body, MAX_BODY_CHARS),
+        }
+    }
+
+    pub fn decode(url: String, error: &serde_json::Error, body: &str) -> ApiError {
+        ApiError::Decode {
+            url,
+            error: error.to_string(),
+            body: truncate(body, MAX_BODY_CHARS),
+        }
+    }
+
+    // label of the fetch error metrics and the error alerts
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ApiError::Transport { .. } => "transport",
+            ApiError::CircuitOpen { .. } => "circuit_open",
+            ApiError::Status { .. } => "http_status",
+            ApiError::Decode { .. } => "decode",
+            ApiError::Api { .. } => "api_code",
+            ApiError::Rebalancing { .. } => "rebalancing",
+        }
+    }
+
+    // worth retrying: the api is unreachable or overloaded
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ApiError::Transport { .. } => true,
+            ApiError::Status { status, .. } => *status >= 500 || *status == 429,
+            _ => false,
+        }
+    }
+
+    // the request failed because of the api or the network, not of the data
+    pub fn is_outage(&self) -> bool {
+        !matches!(self, ApiError::Rebalancing { .. })
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Transport { url, error } => write!(f, "{}, url [{}]", error, url),
+            ApiError::CircuitOpen { url } => write!(f, "circuit open, url [{}]", url),
+            ApiError::Status { url, status, body } => {
+                write!(f, "http status {}, body [{}], url [{}]", status, body, url)
+            }
+            ApiError::Decode { url, error, body } => write!(
+                f,
+                "decode response: {}, body [{}], url [{}]",
+                error, body, url
+            ),
+            ApiError::Api { url, code, message } => write!(
+                f,
+                "api error code {}, message [{}], url [{}]",
+                code, message, url
+            ),
+            ApiError::Rebalancing { url, venue } => {
+                write!(f, "{} balance is rebalancing, url [{}]", venue, url)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+// the kind of an error of the api, "other" for the rest
+pub fn kind_of(e: &anyhow::Error) -> &'static str {
+    e.downcast_ref::<ApiError>()
+        .map(|e| e.kind())
+        .unwrap_or("other")
+}
+
+// false for the errors of an api that answers, like rebalancing
+pub fn is_outage(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<ApiError>()
+        .map(|e| e.is_outage())
+        .unwrap_or(true)
+}
+
+// the error with its kind, for the error alerts
+pub fn describe(e: &anyhow::Error) -> String {
+    format!("[{}] {}", kind_of(e), e)
+}
+
+pub fn truncate(s: &str, max_chars: usize) -> String {
+    if s.chars().count() <= max_chars {
+        return s.to_string();
+    }
+    let mut t: String = s.chars().take(max_chars).collect();
+    t.push_str("...");
+    t
+}
@@ -1,2 +1,6 @@
+pub mod breaker;
 pub mod client;
 pub mod dto;
+pub mod error;
+pub mod recorder;
+mod test;
@@ -0,0 +1,147 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use cex_dex_monitor::redact_url;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+// Record is one raw api response, a recording is a file of one json record per line
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Record {
+    pub at: DateTime<Utc>,
+    // without credentials
+    pub base_url: String,
+    pub path: String,
+    // url encoded, empty without query
+    pub query: String,
+    pub status: u16,
+    pub body: String,
+}
+
+pub fn query_string(query: &[(&str, &str)]) -> String {
+    url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(query)
+        .finish()
+}
+
+// Recorder appends the responses of every client to the recording
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    pub fn open(path: &str) -> anyhow::Result<Recorder> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, r: &Record) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(r)?;
+        line.push('\n');
+        self.file.lock().unwrap().write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+// one recorder per path, shared by every client writing to it
+pub fn for_path(path: &str) -> anyhow::Result<Arc<Recorder>> {
+    static RECORDERS: OnceLock<Mutex<HashMap<String, Arc<Recorder>>>> = OnceLock::new();
+
+    let mut recorders = RECORDERS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap();
+    if let Some(r) = recorders.get(path) {
+        return Ok(r.clone());
+    }
+    let r = Arc::new(Recorder::open(path)?);
+    recorders.insert(path.to_string(), r.clone());
+    Ok(r)
+}
+
+// Replay answers requests from a recording: the last response recorded at or
+// before the time of the request
+#[derive(Default)]
+pub struct Replay {
+    // (base_url, path, query) -> records sorted by time
+    records: HashMap<(String, String, String), Vec<Record>>,
+}
+
+impl Replay {
+    pub fn load(path: &str) -> anyhow::Result<Replay> {
+        let content = fs::read_to_string(path)?;
+        let mut records = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let r: Record = serde_json::from_str(line)
+                .map_err(|e| anyhow::format_err!("{} line {}: {}", path, i + 1, e))?;
+            records.push(r);
+        }
+        Ok(Replay::new(records))
+    }
+
+    pub fn new(records: Vec<Record>) -> Replay {
+        let mut replay = Replay::default();
+        for mut r in records {
+            // recordings may predate the redaction
+            r.base_url = redact_url(&r.base_url);
+            replay
+                .records
+                .entry((r.base_url.clone(), r.path.clone(), r.query.clone()))
+                .or_default()
+                .push(r);
+        }
+        for v in replay.records.values_mut() {
+            v.sort_by_key(|r| r.at);
+        }
+        replay
+    }
+
+    pub fn response(
+        &self,
+        base_url: &str,
+        path: &str,
+        query: &str,
+        now: DateTime<Utc>,
+    ) -> Option<&Record> {
+        self.records
+            .get(&(base_url.to_string(), path.to_string(), query.to_string()))?
+            .iter()
+            .take_while(|r| r.at <= now)
+            .last()
+    }
+
+    // time of the first and the last record
+    pub fn span(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let first = self
+            .records
+            .values()
+            .filter_map(|v| v.first())
+            .map(|r| r.at);
+        let last = self.records.values().filter_map(|v| v.last()).map(|r| r.at);
+        Some((first.min()?, last.max()?))
+    }
+}
+
+thread_local! {
+    static REPLAY: RefCell<Option<Arc<Replay>>> = const { RefCell::new(None) };
+}
+
+// clients created afterwards on this thread answer from the recording instead of
+// the api, a replay runs every monitor on a single thread runtime
+pub fn set_replay(replay: Option<Arc<Replay>>) {
+    REPLAY.with(|r| *r.borrow_mut() = replay);
+}
+
+pub fn replay() -> Option<Arc<Replay>> {
+    REPLAY.with(|r| r.borrow().clone())
+}
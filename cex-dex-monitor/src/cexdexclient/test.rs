@@ -0,0 +1,312 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use cex_dex_monitor::CexDexClientConfig;
+    use rust_decimal_macros::dec;
+
+    use crate::{
+        cexdexclient::{
+            breaker::CircuitBreaker,
+            client::CexDexClient,
+            error::ApiError,
+            recorder::{self, Record},
+        },
+        testutil::{ok, status, MockServer},
+    };
+
+    const DEX_BALANCE: &str = r#"{
+        "result": {"code": 0, "message": "ok"},
+        "data": {"id": "1", "is_rebalancing": false, "balances": {"KNC": 1.5}, "contract_balances": {}}
+    }"#;
+    const DEX_PATH: &str = "/dex/polygon/balances";
+
+    fn client(server: &MockServer, cfg: CexDexClientConfig) -> CexDexClient {
+        CexDexClient::new(
+            server.url.clone(),
+            "user".to_string(),
+            "pass".to_string(),
+            &cfg,
+        )
+    }
+
+    fn fast_config() -> CexDexClientConfig {
+        CexDexClientConfig {
+            timeout_ms: 200,
+            max_retries: 3,
+            backoff_base_ms: 1,
+            backoff_max_ms: 5,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let server = MockServer::start().await;
+        server.respond(DEX_PATH, vec![status(500), status(502), ok(DEX_BALANCE)]);
+
+        let resp = client(&server, fast_config())
+            .get_dex_balanace("polygon")
+            .await
+            .unwrap();
+
+        assert_eq!(resp.data.balances.get("KNC"), Some(&dec!(1.5)));
+        assert_eq!(server.hits(DEX_PATH), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let server = MockServer::start().await;
+        server.respond(DEX_PATH, vec![status(401)]);
+
+        let err = client(&server, fast_config())
+            .get_dex_balanace("polygon")
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(err, ApiError::Status { status: 401, .. }),
+            "{}",
+            err
+        );
+        assert_eq!(server.hits(DEX_PATH), 1);
+    }
+
+    #[tokio::test]
+    async fn non_zero_code_is_an_api_error() {
+        let server = MockServer::start().await;
+        server.respond(
+            DEX_PATH,
+            vec![ok(
+                r#"{"result": {"code": 4001, "message": "unknown chain"}, "data": null}"#,
+            )],
+        );
+
+        let err = client(&server, fast_config())
+            .get_dex_balanace("polygon")
+            .await
+            .unwrap_err();
+
+        match err {
+            ApiError::Api { code, message, .. } => {
+                assert_eq!(code, 4001);
+                assert_eq!(message, "unknown chain");
+            }
+            e => panic!("unexpected error {}", e),
+        }
+        assert_eq!(server.hits(DEX_PATH), 1);
+    }
+
+    #[tokio::test]
+    async fn decode_error_keeps_a_truncated_body() {
+        let server = MockServer::start().await;
+        let body = format!("<html>{}</html>", "x".repeat(1000));
+        server.respond(DEX_PATH, vec![ok(&body)]);
+
+        let err = client(&server, fast_config())
+            .get_dex_balanace("polygon")
+            .await
+            .unwrap_err();
+
+        match &err {
+            ApiError::Decode { body, .. } => {
+                assert!(body.starts_with("<html>xxx"));
+                assert_eq!(body.chars().count(), 256 + 3);
+            }
+            e => panic!("unexpected error {}", e),
+        }
+        assert_eq!(err.kind(), "decode");
+        assert_eq!(server.hits(DEX_PATH), 1);
+    }
+
+    #[tokio::test]
+    async fn rebalancing_is_an_error_but_not_an_outage() {
+        let server = MockServer::start().await;
+        server.respond(
+            DEX_PATH,
+            vec![ok(&DEX_BALANCE.replace(
+                r#""is_rebalancing": false"#,
+                r#""is_rebalancing": true"#,
+            ))],
+        );
+
+        let err = client(&server, fast_config())
+            .get_dex_balanace("polygon")
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(&err, ApiError::Rebalancing { venue, .. } if venue == "dex/polygon"),
+            "{}",
+            err
+        );
+        assert!(!err.is_outage());
+        assert!(!err.is_transient());
+    }
+
+    #[tokio::test]
+    async fn times_out_slow_responses() {
+        let server = MockServer::start().await;
+        server.respond(
+            DEX_PATH,
+            vec![ok(DEX_BALANCE).delayed(Duration::from_secs(2))],
+        );
+        let cfg = CexDexClientConfig {
+            max_retries: 1,
+            ..fast_config()
+        };
+
+        assert!(client(&server, cfg)
+            .get_dex_balanace("polygon")
+            .await
+            .is_err());
+        assert_eq!(server.hits(DEX_PATH), 2);
+    }
+
+    #[tokio::test]
+    async fn open_circuit_fails_fast() {
+        let server = MockServer::start().await;
+        server.respond(DEX_PATH, vec![status(503)]);
+        let cfg = CexDexClientConfig {
+            max_retries: 0,
+            breaker_failure_threshold: 2,
+            breaker_open_secs: 3600,
+            ..fast_config()
+        };
+        let c = client(&server, cfg.clone());
+
+        assert!(c.get_dex_balanace("polygon").await.is_err());
+        assert!(c.get_dex_balanace("polygon").await.is_err());
+        assert_eq!(server.hits(DEX_PATH), 2);
+
+        // the breaker is shared by every client of the base url
+        let err = client(&server, cfg)
+            .get_dex_balanace("polygon")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::CircuitOpen { .. }), "{}", err);
+        assert_eq!(server.hits(DEX_PATH), 2);
+    }
+
+    #[tokio::test]
+    async fn retry_stopped_by_open_circuit_keeps_the_upstream_error() {
+        let server = MockServer::start().await;
+        server.respond(DEX_PATH, vec![status(503)]);
+        let cfg = CexDexClientConfig {
+            breaker_failure_threshold: 1,
+            breaker_open_secs: 3600,
+            ..fast_config()
+        };
+
+        let err = client(&server, cfg)
+            .get_dex_balanace("polygon")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, ApiError::Status { status: 503, .. }),
+            "{}",
+            err
+        );
+        assert_eq!(server.hits(DEX_PATH), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_trial_expires() {
+        let b = CircuitBreaker::new(1, Duration::from_secs(60));
+        b.on_failure();
+        assert!(!b.allow());
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        // the trial request is dropped before it reports a result
+        assert!(b.allow());
+        assert!(!b.allow());
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(b.allow());
+        b.on_success();
+        assert!(b.allow());
+    }
+
+    #[tokio::test]
+    async fn reloaded_breaker_settings_apply() {
+        let server = MockServer::start().await;
+        server.respond(DEX_PATH, vec![status(503), ok(DEX_BALANCE)]);
+        let cfg = CexDexClientConfig {
+            max_retries: 0,
+            breaker_failure_threshold: 1,
+            breaker_open_secs: 3600,
+            ..fast_config()
+        };
+
+        assert!(client(&server, cfg.clone())
+            .get_dex_balanace("polygon")
+            .await
+            .is_err());
+        let err = client(&server, cfg.clone())
+            .get_dex_balanace("polygon")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::CircuitOpen { .. }), "{}", err);
+
+        // a client of the reloaded config shortens the open circuit
+        let reloaded = CexDexClientConfig {
+            breaker_open_secs: 0,
+            ..cfg
+        };
+        assert!(client(&server, reloaded)
+            .get_dex_balanace("polygon")
+            .await
+            .is_ok());
+        assert_eq!(server.hits(DEX_PATH), 2);
+    }
+
+    #[tokio::test]
+    async fn half_open_trial_closes_circuit() {
+        let server = MockServer::start().await;
+        server.respond(DEX_PATH, vec![status(503), ok(DEX_BALANCE)]);
+        let cfg = CexDexClientConfig {
+            max_retries: 0,
+            breaker_failure_threshold: 1,
+            breaker_open_secs: 0,
+            ..fast_config()
+        };
+        let c = client(&server, cfg);
+
+        assert!(c.get_dex_balanace("polygon").await.is_err());
+        assert!(c.get_dex_balanace("polygon").await.is_ok());
+        assert!(c.get_dex_balanace("polygon").await.is_ok());
+        assert_eq!(server.hits(DEX_PATH), 3);
+    }
+
+    #[tokio::test]
+    async fn recordings_have_no_credentials() {
+        let server = MockServer::start().await;
+        server.respond(DEX_PATH, vec![ok(DEX_BALANCE)]);
+        let path = std::env::temp_dir().join(format!(
+            "cex-dex-monitor-recording-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let cfg = CexDexClientConfig {
+            record_path: path.to_string_lossy().to_string(),
+            ..fast_config()
+        };
+        let base_url = server.url.replace("http://", "http://user:pass@");
+
+        CexDexClient::new(base_url, "user".to_string(), "pass".to_string(), &cfg)
+            .get_dex_balanace("polygon")
+            .await
+            .unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let r: Record = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(r.base_url, server.url.replace("http://", "http://***@"));
+        assert!(!content.contains("pass"), "{}", content);
+
+        assert_eq!(
+            recorder::query_string(&[("since", "2024-05-01T00:00:00+07:00"), ("q", "a b&c")]),
+            "since=2024-05-01T00%3A00%3A00%2B07%3A00&q=a+b%26c"
+        );
+    }
+}
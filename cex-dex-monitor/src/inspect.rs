@@ -0,0 +1,473 @@
+use std::{collections::BTreeMap, io::Write};
+
+use cex_dex_monitor::{Config, PnlConfig};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    cexdexclient::{
+        client::CexDexClient,
+        dto::{CexOrderData, DexTxData, StateData},
+    },
+    pnl::{self, StatePnl},
+};
+
+// StateReport is what a state did, for the state and states commands
+#[derive(Serialize, Debug)]
+pub struct StateReport {
+    pub env: String,
+    pub state_id: String,
+    pub token: String,
+    pub side: String,
+    pub cex: String,
+    pub dex: String,
+    pub dex_chain: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub is_done: bool,
+    pub base_amount: Decimal,
+    pub p1_orders: Vec<OrderReport>,
+    pub p2_orders: Vec<OrderReport>,
+    pub p2_txs: Vec<TxReport>,
+    pub asset_change: BTreeMap<String, Decimal>,
+    pub asset_change_with_fee: BTreeMap<String, Decimal>,
+    pub pnl: StatePnl,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OrderReport {
+    pub id: String,
+    pub status: String,
+    pub side: String,
+    pub base_symbol: String,
+    pub quote_symbol: String,
+    pub price: f64,
+    pub price_with_fee: f64,
+    pub filled_base: Decimal,
+    pub filled_quote: Decimal,
+    pub fee_amount: Decimal,
+    pub fee_asset: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub filled_at: Option<DateTime<Utc>>,
+    pub fill_latency_ms: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TxReport {
+    pub tx_hash: String,
+    pub status: String,
+    pub token_in: String,
+    pub amount_in: Decimal,
+    pub token_out: String,
+    pub estimated_amount_out: Decimal,
+    pub actual_amount_out: Decimal,
+    pub gas_used: u64,
+    pub gas_price: f64,
+    pub estimated_at: Option<DateTime<Utc>>,
+    pub broadcasted_at: Option<DateTime<Utc>>,
+    pub mined_at: Option<DateTime<Utc>>,
+    pub mined_block: u64,
+    pub estimate_to_broadcast_ms: Option<i64>,
+    pub broadcast_to_mine_ms: Option<i64>,
+}
+
+impl StateReport {
+    pub fn new(state: &StateData, env: &str, cfg: &PnlConfig) -> StateReport {
+        let orders = |o: &Option<Vec<CexOrderData>>| {
+            o.iter()
+                .flat_map(|v| v.iter())
+                .map(OrderReport::new)
+                .collect::<Vec<OrderReport>>()
+        };
+        let sorted = |m: &Option<std::collections::HashMap<String, Decimal>>| {
+            m.iter()
+                .flat_map(|m| m.iter())
+                .map(|(k, v)| (k.clone(), *v))
+                .collect::<BTreeMap<String, Decimal>>()
+        };
+        StateReport {
+            env: env.to_string(),
+            state_id: state.state_id.clone(),
+            token: state.token.clone(),
+            side: state.side.clone(),
+            cex: state.cex.clone(),
+            dex: state.dex.clone(),
+            dex_chain: state.dex_chain.clone(),
+            created_at: state.created_at(),
+            is_done: state.is_done,
+            base_amount: state.base_amount,
+            p1_orders: orders(&state.p1_cex_orders),
+            p2_orders: orders(&state.p2_cex_orders),
+            p2_txs: state
+                .p2_dex_txs
+                .iter()
+                .flat_map(|v| v.iter())
+                .map(TxReport::new)
+                .collect(),
+            asset_change: sorted(&state.asset_change),
+            asset_change_with_fee: sorted(&state.asset_change_with_fee),
+            pnl: pnl::compute(state, env, cfg),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut s = format!(
+            "STATE {} ({})\n{} {}, {} <-> {}/{}\ncreated {}, {}, base amount {}\n",
+            self.state_id,
+            self.env,
+            self.token,
+            self.side,
+            self.cex,
+            self.dex,
+            self.dex_chain,
+            time(&self.created_at),
+            if self.is_done { "done" } else { "running" },
+            self.base_amount
+        );
+
+        for (title, orders) in [
+            ("P1 CEX ORDERS", &self.p1_orders),
+            ("P2 CEX ORDERS", &self.p2_orders),
+        ] {
+            s.push_str(&format!("\n{} ({})\n", title, orders.len()));
+            for o in orders {
+                s.push_str(&o.render());
+            }
+        }
+
+        s.push_str(&format!("\nP2 DEX TXS ({})\n", self.p2_txs.len()));
+        for tx in self.p2_txs.iter() {
+            s.push_str(&tx.render());
+        }
+
+        s.push_str("\nASSET CHANGES\n");
+        for (asset, change) in self.asset_change.iter() {
+            let with_fee = self
+                .asset_change_with_fee
+                .get(asset)
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "-".to_string());
+            s.push_str(&format!(
+                "- {}: {} (with fee {})\n",
+                asset, change, with_fee
+            ));
+        }
+        for (asset, with_fee) in self.asset_change_with_fee.iter() {
+            if !self.asset_change.contains_key(asset) {
+                s.push_str(&format!("- {}: - (with fee {})\n", asset, with_fee));
+            }
+        }
+
+        let p = &self.pnl;
+        s.push_str(&format!(
+            "\nPNL ({}): gross {}, cex fee {}, gas {}, net {}\n",
+            p.quote, p.gross, p.cex_fee, p.gas, p.net
+        ));
+        if !p.unpriced_fees.is_empty() {
+            s.push_str(&format!("unpriced fees: {}\n", p.unpriced_fees));
+        }
+        s
+    }
+
+    // one line, for the states command
+    pub fn summary(&self) -> String {
+        format!(
+            "{} {} {} {} {} p1 {} p2 {} txs {} net {} {}",
+            time(&self.created_at),
+            self.state_id,
+            self.token,
+            self.side,
+            if self.is_done { "done" } else { "running" },
+            self.p1_orders.len(),
+            self.p2_orders.len(),
+            self.p2_txs.len(),
+            self.pnl.net,
+            self.pnl.quote
+        )
+    }
+}
+
+impl OrderReport {
+    fn new(o: &CexOrderData) -> OrderReport {
+        OrderReport {
+            id: o.id.clone(),
+            status: o.status.clone(),
+            side: o.side.clone(),
+            base_symbol: o.base_symbol.clone(),
+            quote_symbol: o.quote_symbol.clone(),
+            price: o.actual_price,
+            price_with_fee: o.actual_price_with_fee,
+            filled_base: o.filled_base_amount,
+            filled_quote: o.filled_quote_amount,
+            fee_amount: o.fee_amount,
+            fee_asset: o.fee_asset.clone(),
+            created_at: millis(o.created_time),
+            filled_at: millis(o.filled_at),
+            fill_latency_ms: o.fill_latency_ms(),
+        }
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "- {} {} {} {} {} for {} {} @ {} ({} with fee), fee {} {}\n  created {} -> filled {} ({})\n",
+            self.id,
+            self.status,
+            self.side,
+            self.filled_base,
+            self.base_symbol,
+            self.filled_quote,
+            self.quote_symbol,
+            self.price,
+            self.price_with_fee,
+            self.fee_amount,
+            self.fee_asset,
+            time(&self.created_at),
+            time(&self.filled_at),
+            latency(self.fill_latency_ms)
+        )
+    }
+}
+
+impl TxReport {
+    fn new(tx: &DexTxData) -> TxReport {
+        TxReport {
+            tx_hash: tx.tx_hash.clone(),
+            status: tx.status.clone(),
+            token_in: tx.token_in.clone(),
+            amount_in: tx.amount_in,
+            token_out: tx.token_out.clone(),
+            estimated_amount_out: tx.estimated_amount_out,
+            actual_amount_out: tx.actual_amount_out,
+            gas_used: tx.gas_used,
+            gas_price: tx.gas_price,
+            estimated_at: millis(tx.estimated_at),
+            broadcasted_at: millis(tx.broadcasted_at),
+            mined_at: millis(tx.mined_at),
+            mined_block: tx.mined_block,
+            estimate_to_broadcast_ms: tx.estimate_to_broadcast_ms(),
+            broadcast_to_mine_ms: tx.broadcast_to_mine_ms(),
+        }
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "- {} {} {} {} -> {} {} (estimated {}), gas {} @ {}, block {}\n  estimated {} -> broadcasted {} ({}) -> mined {} ({})\n",
+            self.tx_hash,
+            self.status,
+            self.amount_in,
+            self.token_in,
+            self.actual_amount_out,
+            self.token_out,
+            self.estimated_amount_out,
+            self.gas_used,
+            self.gas_price,
+            self.mined_block,
+            time(&self.estimated_at),
+            time(&self.broadcasted_at),
+            latency(self.estimate_to_broadcast_ms),
+            time(&self.mined_at),
+            latency(self.broadcast_to_mine_ms)
+        )
+    }
+}
+
+// unix millis, 0 when not yet
+fn millis(t: i64) -> Option<DateTime<Utc>> {
+    if t == 0 {
+        return None;
+    }
+    DateTime::from_timestamp_millis(t)
+}
+
+fn time(t: &Option<DateTime<Utc>>) -> String {
+    match t {
+        None => "-".to_string(),
+        Some(t) => t.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+    }
+}
+
+fn latency(ms: Option<i64>) -> String {
+    match ms {
+        None => "-".to_string(),
+        Some(ms) => format!("{}ms", ms),
+    }
+}
+
+// the clients of every url of the env, the recording is left alone
+fn clients(cfg: &Config, env: &str) -> anyhow::Result<Vec<CexDexClient>> {
+    let mut client_cfg = cfg.cex_dex_client.clone();
+    client_cfg.record_path.clear();
+    let clients = cfg
+        .cex_dex_config
+        .iter()
+        .filter(|v| v.env.eq(env))
+        .flat_map(|v| v.urls.iter())
+        .map(|u| {
+            CexDexClient::new(
+                u.base_url.clone(),
+                u.user.clone(),
+                u.pass.clone(),
+                &client_cfg,
+            )
+        })
+        .collect::<Vec<CexDexClient>>();
+    if clients.is_empty() {
+        anyhow::bail!("no url for env {}", env);
+    }
+    Ok(clients)
+}
+
+// the last size done and running states of every url of the env
+async fn fetch_states(cfg: &Config, env: &str, size: usize) -> anyhow::Result<Vec<StateData>> {
+    let mut states = Vec::new();
+    for c in clients(cfg, env)? {
+        for is_done in [true, false] {
+            states.extend(c.get_filled_states(is_done, size).await?.data);
+        }
+    }
+    Ok(states)
+}
+
+fn runtime() -> anyhow::Result<tokio::runtime::Runtime> {
+    Ok(tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?)
+}
+
+// prints one state, searched in the last size states of each url
+pub fn state(
+    cfg: &Config,
+    env: &str,
+    state_id: &str,
+    size: usize,
+    json: bool,
+    mut out: impl Write,
+) -> anyhow::Result<()> {
+    let states = runtime()?.block_on(fetch_states(cfg, env, size))?;
+    let state = states
+        .iter()
+        .find(|s| s.state_id.eq(state_id))
+        .ok_or_else(|| {
+            anyhow::format_err!(
+                "state {} not in the last {} states of {}",
+                state_id,
+                size,
+                env
+            )
+        })?;
+
+    let report = StateReport::new(state, env, &cfg.pnl);
+    if json {
+        serde_json::to_writer_pretty(&mut out, &report)?;
+        writeln!(out)?;
+    } else {
+        write!(out, "{}", report.render())?;
+    }
+    Ok(())
+}
+
+// prints the states created since, oldest first
+pub fn states(
+    cfg: &Config,
+    env: &str,
+    since: DateTime<Utc>,
+    size: usize,
+    json: bool,
+    mut out: impl Write,
+) -> anyhow::Result<()> {
+    let states = runtime()?.block_on(fetch_states(cfg, env, size))?;
+    let mut reports = select_since(&states, since)
+        .into_iter()
+        .map(|s| StateReport::new(s, env, &cfg.pnl))
+        .collect::<Vec<StateReport>>();
+    reports.sort_by(|a, b| (a.created_at, &a.state_id).cmp(&(b.created_at, &b.state_id)));
+
+    if json {
+        serde_json::to_writer_pretty(&mut out, &reports)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    for r in reports {
+        writeln!(out, "{}", r.summary())?;
+    }
+    Ok(())
+}
+
+// a state listed by several urls is kept once, one without a creation time is skipped
+fn select_since(states: &[StateData], since: DateTime<Utc>) -> Vec<&StateData> {
+    let mut seen = std::collections::HashSet::new();
+    states
+        .iter()
+        .filter(|s| s.created_at().is_some_and(|t| t >= since))
+        .filter(|s| seen.insert(s.state_id.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use cex_dex_monitor::PnlConfig;
+    use chrono::DateTime;
+
+    use super::{select_since, StateReport};
+    use crate::testutil;
+
+    fn state() -> crate::cexdexclient::dto::StateData {
+        let mut s = testutil::state();
+        let mut tx = testutil::tx();
+        tx.estimated_at = 1705572001000;
+        tx.broadcasted_at = 1705572001250;
+        tx.mined_at = 1705572004000;
+        tx.mined_block = 42;
+        s.p2_dex_txs = Some(vec![tx]);
+        let mut order = testutil::order("USDT", rust_decimal_macros::dec!(0.02));
+        order.created_time = 1705572000000;
+        order.filled_at = 1705572000400;
+        s.p1_cex_orders = Some(vec![order]);
+        s
+    }
+
+    #[test]
+    fn render_shows_orders_txs_and_latencies() {
+        let text = StateReport::new(&state(), "prod", &PnlConfig::default()).render();
+
+        assert!(text.starts_with("STATE s1 (prod)\nKNC BUY, binance <-> kyberswap/polygon\n"));
+        assert!(text.contains(
+            "\nP1 CEX ORDERS (1)\n- o FILLED BUY 10 KNC for 20 USDT @ 2 (2 with fee), fee 0.02 USDT\n  \
+             created 2024-01-18T10:00:00.000Z -> filled 2024-01-18T10:00:00.400Z (400ms)\n"
+        ));
+        assert!(text.contains(
+            "  estimated 2024-01-18T10:00:01.000Z -> broadcasted 2024-01-18T10:00:01.250Z (250ms) \
+             -> mined 2024-01-18T10:00:04.000Z (2750ms)\n"
+        ));
+        assert!(text.contains("\nASSET CHANGES\n- KNC: 0.1 (with fee -)\n- USDT: 1 (with fee -)\n"));
+        assert!(text.contains("\nPNL (USDT): gross "));
+    }
+
+    #[test]
+    fn json_has_the_latencies() {
+        let v = serde_json::to_value(StateReport::new(&state(), "prod", &PnlConfig::default()))
+            .unwrap();
+
+        assert_eq!(v["p1_orders"][0]["fill_latency_ms"], 400);
+        assert_eq!(v["p2_txs"][0]["broadcast_to_mine_ms"], 2750);
+        assert_eq!(v["p2_txs"][0]["mined_at"], "2024-01-18T10:00:04Z");
+        assert_eq!(v["p2_orders"][0]["filled_at"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn since_skips_older_and_duplicate_states() {
+        let mut old = testutil::state();
+        old.state_id = "s0".to_string();
+        old.created_time = "2024-01-17T10:00:00Z".to_string();
+        let states = vec![old, testutil::state(), testutil::state()];
+
+        let since = DateTime::parse_from_rfc3339("2024-01-18T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let ids = select_since(&states, since)
+            .iter()
+            .map(|s| s.state_id.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(ids, vec!["s1"]);
+    }
+}
@@ -0,0 +1,375 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    sync::{Mutex, OnceLock},
+};
+
+use cex_dex_monitor::LatencyConfig;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{cexdexclient::dto::StateData, slackclient::message::Message};
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    // created -> filled of a cex order
+    CexFillMs,
+    // broadcasted -> mined of a dex tx
+    DexMineMs,
+    // gas of a state, in pnl quote asset
+    Gas,
+}
+
+impl Metric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::CexFillMs => "cex fill latency (ms)",
+            Metric::DexMineMs => "dex broadcast to mine latency (ms)",
+            Metric::Gas => "gas per state",
+        }
+    }
+
+    // 0: no alert
+    fn baseline_p95(&self, cfg: &LatencyConfig) -> f64 {
+        match self {
+            Metric::CexFillMs => cfg.cex_fill_p95_ms as f64,
+            Metric::DexMineMs => cfg.dex_mine_p95_ms as f64,
+            Metric::Gas => cfg.gas_p95,
+        }
+    }
+}
+
+// StateLatency is what one state adds to the stats, orders and txs not done yet
+// have no latency
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct StateLatency {
+    pub cex_fill_ms: Vec<i64>,
+    pub dex_mine_ms: Vec<i64>,
+    pub gas: f64,
+}
+
+impl StateLatency {
+    // gas: the gas of the state valued by pnl::compute
+    pub fn new(state: &StateData, gas: f64) -> StateLatency {
+        StateLatency {
+            cex_fill_ms: state
+                .p1_cex_orders
+                .iter()
+                .chain(state.p2_cex_orders.iter())
+                .flat_map(|v| v.iter())
+                .filter_map(|o| o.fill_latency_ms())
+                .collect(),
+            dex_mine_ms: state
+                .p2_dex_txs
+                .iter()
+                .flat_map(|v| v.iter())
+                .filter_map(|tx| tx.broadcast_to_mine_ms())
+                .collect(),
+            gas,
+        }
+    }
+
+    fn samples(&self) -> Vec<(Metric, f64)> {
+        let mut samples = Vec::new();
+        for ms in self.cex_fill_ms.iter() {
+            samples.push((Metric::CexFillMs, *ms as f64));
+        }
+        for ms in self.dex_mine_ms.iter() {
+            samples.push((Metric::DexMineMs, *ms as f64));
+        }
+        samples.push((Metric::Gas, self.gas));
+        samples
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Percentiles {
+    pub samples: usize,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+impl Percentiles {
+    // nearest rank, None without values
+    pub fn of(values: &[f64]) -> Option<Percentiles> {
+        if values.is_empty() {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_unstable_by(|a, b| a.total_cmp(b));
+        let rank = |p: f64| {
+            let i = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+            sorted[i.clamp(1, sorted.len()) - 1]
+        };
+        Some(Percentiles {
+            samples: sorted.len(),
+            p50: rank(50.0),
+            p95: rank(95.0),
+            p99: rank(99.0),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LatencyEvent {
+    Degraded {
+        metric: Metric,
+        p95: f64,
+        baseline: f64,
+        samples: usize,
+    },
+    Recovered {
+        metric: Metric,
+        p95: f64,
+        baseline: f64,
+        samples: usize,
+    },
+}
+
+pub fn build_message(env: &str, e: &LatencyEvent, window_secs: u64) -> Message {
+    let (title, metric, p95, baseline, samples) = match e {
+        LatencyEvent::Degraded {
+            metric,
+            p95,
+            baseline,
+            samples,
+        } => ("LATENCY DEGRADED", metric, p95, baseline, samples),
+        LatencyEvent::Recovered {
+            metric,
+            p95,
+            baseline,
+            samples,
+        } => ("LATENCY RECOVERED", metric, p95, baseline, samples),
+    };
+    let window = format!("{} samples in {}m", samples, window_secs / 60);
+    let fallback = format!(
+        "*****\n*{}*\n> ENV: {}\n{}: p95 {} (baseline {})\n{}\n*****",
+        title,
+        env,
+        metric.name(),
+        p95,
+        baseline,
+        window
+    );
+    Message::builder(fallback)
+        .header(title)
+        .fields(vec![
+            ("ENV", env.to_string()),
+            ("METRIC", metric.name().to_string()),
+            ("P95", p95.to_string()),
+            ("BASELINE", baseline.to_string()),
+        ])
+        .context(&window)
+        .build()
+}
+
+// Rolling keeps the samples of the last window of an env, and which metrics
+// have a p95 above their baseline
+#[derive(Default)]
+pub struct Rolling {
+    window: chrono::Duration,
+    samples: BTreeMap<Metric, VecDeque<(DateTime<Utc>, f64)>>,
+    degraded: BTreeSet<Metric>,
+}
+
+impl Rolling {
+    pub fn record(
+        &mut self,
+        state: &StateLatency,
+        now: DateTime<Utc>,
+        cfg: &LatencyConfig,
+    ) -> Vec<LatencyEvent> {
+        self.window = chrono::Duration::seconds(cfg.window_secs as i64);
+        for (metric, value) in state.samples() {
+            self.samples
+                .entry(metric)
+                .or_default()
+                .push_back((now, value));
+        }
+        self.prune(now);
+
+        let mut events = Vec::new();
+        for (metric, p) in self.percentiles() {
+            let baseline = metric.baseline_p95(cfg);
+            if baseline <= 0.0 || p.samples < cfg.min_samples {
+                continue;
+            }
+            let degraded = p.p95 > baseline;
+            if degraded == self.degraded.contains(&metric) {
+                continue;
+            }
+            let (p95, samples) = (p.p95, p.samples);
+            if degraded {
+                self.degraded.insert(metric);
+                events.push(LatencyEvent::Degraded {
+                    metric,
+                    p95,
+                    baseline,
+                    samples,
+                });
+            } else {
+                self.degraded.remove(&metric);
+                events.push(LatencyEvent::Recovered {
+                    metric,
+                    p95,
+                    baseline,
+                    samples,
+                });
+            }
+        }
+        events
+    }
+
+    fn prune(&mut self, now: DateTime<Utc>) {
+        for samples in self.samples.values_mut() {
+            while samples
+                .front()
+                .is_some_and(|(at, _)| now - *at > self.window)
+            {
+                samples.pop_front();
+            }
+        }
+    }
+
+    pub fn percentiles(&self) -> BTreeMap<Metric, Percentiles> {
+        self.samples
+            .iter()
+            .filter_map(|(metric, samples)| {
+                let values = samples.iter().map(|(_, v)| *v).collect::<Vec<f64>>();
+                Some((*metric, Percentiles::of(&values)?))
+            })
+            .collect()
+    }
+}
+
+// the rolling stats of every env, fed by the monitors of its urls
+pub struct Stats {
+    envs: Mutex<HashMap<String, Rolling>>,
+}
+
+static STATS: OnceLock<Stats> = OnceLock::new();
+
+pub fn get() -> &'static Stats {
+    STATS.get_or_init(|| Stats {
+        envs: Mutex::new(HashMap::new()),
+    })
+}
+
+impl Stats {
+    pub fn record(
+        &self,
+        env: &str,
+        state: &StateLatency,
+        now: DateTime<Utc>,
+        cfg: &LatencyConfig,
+    ) -> Vec<LatencyEvent> {
+        self.envs
+            .lock()
+            .unwrap()
+            .entry(env.to_string())
+            .or_default()
+            .record(state, now, cfg)
+    }
+
+    // env -> metric -> percentiles, of the samples kept at the last record
+    pub fn report(&self) -> BTreeMap<String, BTreeMap<Metric, Percentiles>> {
+        self.envs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(env, r)| (env.clone(), r.percentiles()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cex_dex_monitor::LatencyConfig;
+
+    use super::{LatencyEvent, Metric, Percentiles, Rolling, StateLatency};
+    use crate::testutil;
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let values = (1..=100).map(|v| v as f64).collect::<Vec<f64>>();
+        assert_eq!(
+            Percentiles::of(&values),
+            Some(Percentiles {
+                samples: 100,
+                p50: 50.0,
+                p95: 95.0,
+                p99: 99.0
+            })
+        );
+        assert_eq!(Percentiles::of(&[7.0]).unwrap().p95, 7.0);
+        assert_eq!(Percentiles::of(&[]), None);
+    }
+
+    #[test]
+    fn state_latency_skips_unfinished_orders_and_txs() {
+        let mut state = testutil::state();
+        let mut filled = testutil::order("USDT", rust_decimal::Decimal::ZERO);
+        filled.created_time = 1_000;
+        filled.filled_at = 1_250;
+        state.p1_cex_orders = Some(vec![filled]);
+        let mut mined = testutil::tx();
+        mined.broadcasted_at = 10_000;
+        mined.mined_at = 12_000;
+        state.p2_dex_txs = Some(vec![mined, testutil::tx()]);
+
+        assert_eq!(
+            StateLatency::new(&state, 0.5),
+            StateLatency {
+                cex_fill_ms: vec![250],
+                dex_mine_ms: vec![2000],
+                gas: 0.5
+            }
+        );
+    }
+
+    #[test]
+    fn alerts_when_p95_crosses_the_baseline() {
+        let t0 = chrono::Utc::now();
+        let at = |secs| t0 + chrono::Duration::seconds(secs);
+        let cfg = LatencyConfig {
+            window_secs: 600,
+            min_samples: 3,
+            dex_mine_p95_ms: 5000,
+            ..Default::default()
+        };
+        let mine = |ms| StateLatency {
+            dex_mine_ms: vec![ms],
+            ..Default::default()
+        };
+        let mut r = Rolling::default();
+
+        // not enough samples yet
+        assert!(r.record(&mine(9000), at(0), &cfg).is_empty());
+        assert!(r.record(&mine(9000), at(10), &cfg).is_empty());
+        assert_eq!(
+            r.record(&mine(1000), at(20), &cfg),
+            vec![LatencyEvent::Degraded {
+                metric: Metric::DexMineMs,
+                p95: 9000.0,
+                baseline: 5000.0,
+                samples: 3
+            }]
+        );
+        // once per degradation
+        assert!(r.record(&mine(9000), at(30), &cfg).is_empty());
+
+        // the slow ones leave the window
+        assert!(r.record(&mine(1000), at(605), &cfg).is_empty());
+        assert!(r.record(&mine(1000), at(615), &cfg).is_empty());
+        assert_eq!(
+            r.record(&mine(1000), at(635), &cfg),
+            vec![LatencyEvent::Recovered {
+                metric: Metric::DexMineMs,
+                p95: 1000.0,
+                baseline: 5000.0,
+                samples: 3
+            }]
+        );
+    }
+}
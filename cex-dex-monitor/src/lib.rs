@@ -1,12 +1,430 @@
-mod slackclient;
+pub mod slackclient;
 
+use rust_decimal::Decimal;
 use serde::Deserialize;
-use std::{error::Error, fs};
+use std::{collections::HashMap, error::Error, fmt, fs};
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Config {
     pub cex_dex_config: Vec<CexDexConfig>,
     pub slack_client_config: slackclient::client::SlackClientConfig,
+    #[serde(default)]
+    pub state_store: StateStoreConfig,
+    #[serde(default)]
+    pub alert_routing: AlertRoutingConfig,
+    // dex chain -> tx url prefix, the tx hash is appended
+    #[serde(default = "default_block_explorers")]
+    pub block_explorers: HashMap<String, String>,
+    // no metrics server when missing
+    pub metrics: Option<MetricsConfig>,
+    // no admin server when missing
+    pub admin: Option<AdminConfig>,
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub cex_dex_client: CexDexClientConfig,
+    #[serde(default)]
+    pub pnl: PnlConfig,
+    #[serde(default)]
+    pub timeline: TimelineConfig,
+    #[serde(default)]
+    pub anomaly: AnomalyConfig,
+    #[serde(default)]
+    pub latency: LatencyConfig,
+    #[serde(default)]
+    pub watchdog: WatchdogConfig,
+    #[serde(default)]
+    pub assets: AssetsConfig,
+    #[serde(default)]
+    pub pricing: PricingConfig,
+    #[serde(default)]
+    pub alert_queue: AlertQueueConfig,
+    // destinations besides the slack webhooks, routes refer to them by name
+    #[serde(default)]
+    pub notifiers: Vec<NotifierConfig>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct NotifierConfig {
+    // used in the channels of the routes, must not clash with a slack channel
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: NotifierKind,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum NotifierKind {
+    // sendMessage of a bot to a chat
+    Telegram {
+        bot_token: String,
+        chat_id: String,
+        #[serde(default = "default_telegram_api_url")]
+        api_url: String,
+    },
+    // POST of the alert as json
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    // plain smtp to a local relay, no tls nor auth
+    Smtp {
+        host: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        from: String,
+        to: Vec<String>,
+    },
+}
+
+fn default_telegram_api_url() -> String {
+    String::from("https://api.telegram.org")
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct AlertQueueConfig {
+    // state done alerts of a channel within this window are sent as one digest, 0: no batching
+    pub batch_window_secs: u64,
+    pub max_batch: usize,
+    // an identical message to the same channel within this window is dropped, 0: no dedup
+    pub dedup_window_secs: u64,
+    // messages per minute per channel, 0: no limit
+    pub max_per_minute: usize,
+    // failed posts are retried with exponential backoff, then dropped
+    pub max_retries: u32,
+    pub retry_base_ms: u64,
+    pub retry_max_ms: u64,
+    // alerts waiting to be sent, new alerts are rejected when full
+    pub capacity: usize,
+}
+
+impl Default for AlertQueueConfig {
+    fn default() -> Self {
+        AlertQueueConfig {
+            batch_window_secs: 30,
+            max_batch: 10,
+            dedup_window_secs: 300,
+            max_per_minute: 20,
+            max_retries: 5,
+            retry_base_ms: 1000,
+            retry_max_ms: 60_000,
+            capacity: 1000,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct PricingConfig {
+    pub kind: PriceProviderKind,
+    // usd price per asset, with the http provider only for the assets it does not return
+    pub prices: HashMap<String, Decimal>,
+    // http provider: GET <url>?symbols=A,B returns {"A": price, "B": price}
+    pub url: String,
+    pub timeout_ms: u64,
+}
+
+impl Default for PricingConfig {
+    fn default() -> Self {
+        PricingConfig {
+            kind: PriceProviderKind::default(),
+            prices: ["USDT", "USDC", "BUSD", "DAI"]
+                .into_iter()
+                .map(|a| (String::from(a), Decimal::ONE))
+                .collect(),
+            url: String::new(),
+            timeout_ms: 5000,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceProviderKind {
+    #[default]
+    Fixed,
+    Http,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AssetsConfig {
+    // applies to the assets missing from per_asset
+    pub default: AssetConfig,
+    pub per_asset: HashMap<String, AssetConfig>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct AssetConfig {
+    // decimal places kept in balances and messages
+    pub precision: u32,
+    // smaller balance changes are not reported
+    pub diff_threshold: Decimal,
+}
+
+impl Default for AssetConfig {
+    fn default() -> Self {
+        AssetConfig {
+            precision: 8,
+            diff_threshold: Decimal::new(1, 5),
+        }
+    }
+}
+
+impl AssetsConfig {
+    pub fn get(&self, asset: &str) -> &AssetConfig {
+        self.per_asset.get(asset).unwrap_or(&self.default)
+    }
+
+    pub fn round(&self, asset: &str, amount: Decimal) -> Decimal {
+        amount.round_dp(self.get(asset).precision).normalize()
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct WatchdogConfig {
+    // a running state older than this is stuck
+    pub max_state_age_secs: u64,
+    // a broadcasted dex tx not mined after this is pending
+    pub max_pending_tx_secs: u64,
+    // running states of one url
+    pub max_running_states: usize,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            max_state_age_secs: 600,
+            max_pending_tx_secs: 180,
+            max_running_states: 5,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct AnomalyConfig {
+    // the state slippage divided by this is a fraction (1: fraction, 100: percent, 10000: bps)
+    pub slippage_scale: f64,
+    // fraction of the p1 price
+    pub max_p2_price_worse: f64,
+    // fraction of the larger of p1 and p2 base amounts
+    pub max_base_imbalance: f64,
+    // prepended to anomaly alerts, e.g. <!here>
+    pub mention: String,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        AnomalyConfig {
+            slippage_scale: 1.0,
+            max_p2_price_worse: 0.005,
+            max_base_imbalance: 0.001,
+            mention: String::from("<!here>"),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct LatencyConfig {
+    // percentiles are computed over the states of this last period
+    pub window_secs: u64,
+    // no alert with fewer samples in the window
+    pub min_samples: usize,
+    // alert when the p95 goes above, 0: no alert
+    pub cex_fill_p95_ms: u64,
+    pub dex_mine_p95_ms: u64,
+    // gas of a state in pnl quote asset
+    pub gas_p95: f64,
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        LatencyConfig {
+            window_secs: 6 * 3600,
+            min_samples: 20,
+            cex_fill_p95_ms: 0,
+            dex_mine_p95_ms: 0,
+            gas_p95: 0.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct PnlConfig {
+    // profit is valued in this asset
+    pub quote_asset: String,
+    // gas_price of the dex txs is in 10^-decimals native token (9: gwei)
+    pub gas_price_decimals: i32,
+    // one row per finished state
+    pub csv_path: String,
+    // one row per day, env, token and side
+    pub daily_csv_path: String,
+}
+
+impl Default for PnlConfig {
+    fn default() -> Self {
+        PnlConfig {
+            quote_asset: String::from("USDT"),
+            gas_price_decimals: 9,
+            csv_path: String::from("pnl_states.csv"),
+            daily_csv_path: String::from("pnl_daily.csv"),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct TimelineConfig {
+    // sqlite file with every fetched balance, empty: none kept
+    pub path: String,
+    // chart of the last 7 days of every asset, sent on monday
+    pub weekly_chart: bool,
+    // ttf of the texts of the png and svg charts
+    pub font_path: String,
+    // bot token with files:write, the weekly chart is then uploaded as png charts to
+    // slack_channel_id instead of sent as sparklines through the routes
+    pub slack_bot_token: String,
+    pub slack_channel_id: String,
+    pub slack_api_url: String,
+}
+
+impl Default for TimelineConfig {
+    fn default() -> Self {
+        TimelineConfig {
+            path: String::from("balances.db"),
+            weekly_chart: true,
+            font_path: String::from("/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf"),
+            slack_bot_token: String::new(),
+            slack_channel_id: String::new(),
+            slack_api_url: String::from("https://slack.com/api"),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CexDexClientConfig {
+    pub timeout_ms: u64,
+    // retries of transient errors (timeouts, connection errors, 5xx, 429)
+    pub max_retries: u32,
+    pub backoff_base_ms: u64,
+    pub backoff_max_ms: u64,
+    // consecutive failures of a base url before its circuit opens
+    pub breaker_failure_threshold: u32,
+    pub breaker_open_secs: u64,
+    // send an error alert once an endpoint has been failing this long
+    pub outage_alert_after_secs: u64,
+    // send an alert once a venue has been rebalancing this long
+    pub rebalancing_alert_after_secs: u64,
+    // every response is appended to this file for replays, empty: not recorded
+    pub record_path: String,
+}
+
+impl Default for CexDexClientConfig {
+    fn default() -> Self {
+        CexDexClientConfig {
+            timeout_ms: 10_000,
+            max_retries: 3,
+            backoff_base_ms: 500,
+            backoff_max_ms: 10_000,
+            breaker_failure_threshold: 5,
+            breaker_open_secs: 60,
+            outage_alert_after_secs: 300,
+            rebalancing_alert_after_secs: 1800,
+            record_path: String::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    // poll of the done states, per url
+    pub state_poll: IntervalConfig,
+    // poll of the running states, per url
+    pub running_poll: IntervalConfig,
+    // between two balance fetches of the stability check
+    pub balance_fetch: IntervalConfig,
+    // between two balance checks
+    pub balance_check: IntervalConfig,
+    // how long the monitors get to finish in-flight work on shutdown
+    pub shutdown_grace_secs: u64,
+    // between two checks of the config file for changes
+    pub config_poll: IntervalConfig,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        SchedulerConfig {
+            state_poll: IntervalConfig {
+                period_secs: 10,
+                jitter_secs: 10,
+            },
+            running_poll: IntervalConfig {
+                period_secs: 30,
+                jitter_secs: 5,
+            },
+            balance_fetch: IntervalConfig {
+                period_secs: 20,
+                jitter_secs: 5,
+            },
+            balance_check: IntervalConfig {
+                period_secs: 600,
+                jitter_secs: 60,
+            },
+            shutdown_grace_secs: 30,
+            config_poll: IntervalConfig {
+                period_secs: 10,
+                jitter_secs: 0,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct IntervalConfig {
+    pub period_secs: u64,
+    #[serde(default)]
+    pub jitter_secs: u64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AdminConfig {
+    // e.g. 127.0.0.1:9101
+    pub listen_addr: String,
+    // expected as Authorization: Bearer <token>
+    pub token: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct MetricsConfig {
+    // e.g. 0.0.0.0:9100
+    pub listen_addr: String,
+}
+
+fn default_block_explorers() -> HashMap<String, String> {
+    HashMap::from([
+        (
+            String::from("polygon"),
+            String::from("https://polygonscan.com/tx/"),
+        ),
+        (
+            String::from("ethereum"),
+            String::from("https://etherscan.io/tx/"),
+        ),
+        (String::from("bsc"), String::from("https://bscscan.com/tx/")),
+    ])
 }
 
 #[derive(serde::Serialize, Deserialize, Clone)]
@@ -20,6 +438,156 @@ pub struct URL {
     pub base_url: String,
     pub user: String,
     pub pass: String,
+    // a cex account listed under several urls of an env is fetched once, from the first url
+    #[serde(default = "default_cexes")]
+    pub cexes: Vec<String>,
+    #[serde(default = "default_dex_chains")]
+    pub dex_chains: Vec<String>,
+}
+
+fn default_cexes() -> Vec<String> {
+    vec![String::from("binance")]
+}
+
+fn default_dex_chains() -> Vec<String> {
+    vec![String::from("polygon")]
+}
+
+impl URL {
+    // base url without credentials, safe to log
+    pub fn redacted(&self) -> String {
+        redact_url(&self.base_url)
+    }
+}
+
+// the pass is never printed
+impl fmt::Debug for URL {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("URL")
+            .field("base_url", &self.redacted())
+            .field("user", &self.user)
+            .field("pass", &"***")
+            .field("cexes", &self.cexes)
+            .field("dex_chains", &self.dex_chains)
+            .finish()
+    }
+}
+
+// drops the credentials of an url, "http://u:p@host/x" -> "http://***@host/x"
+pub fn redact_url(url: &str) -> String {
+    let start = url.find("://").map(|i| i + 3).unwrap_or(0);
+    let rest = &url[start..];
+    // the credentials are before the path, the query or the fragment
+    let authority = &rest[..rest.find(['/', '?', '#']).unwrap_or(rest.len())];
+    match authority.rfind('@') {
+        Some(at) => format!("{}***{}", &url[..start], &rest[at..]),
+        None => url.to_string(),
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct StateStoreConfig {
+    #[serde(default)]
+    pub kind: StateStoreKind,
+    #[serde(default = "default_state_store_path")]
+    pub path: String,
+}
+
+impl Default for StateStoreConfig {
+    fn default() -> Self {
+        StateStoreConfig {
+            kind: StateStoreKind::default(),
+            path: default_state_store_path(),
+        }
+    }
+}
+
+fn default_state_store_path() -> String {
+    String::from("state.json")
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum StateStoreKind {
+    #[default]
+    Json,
+    Memory,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AlertRoutingConfig {
+    pub routes: Vec<AlertRouteConfig>,
+}
+
+// without a routing section every alert but errors goes to the legacy channel
+impl Default for AlertRoutingConfig {
+    fn default() -> Self {
+        let channel = String::from("alert-virtual-taker-1");
+        AlertRoutingConfig {
+            routes: [
+                AlertKind::StateDone,
+                AlertKind::BalanceDiff1h,
+                AlertKind::BalanceDiff24h,
+                AlertKind::BalanceSnapshot,
+                AlertKind::PnlDaily,
+                AlertKind::Anomaly,
+                AlertKind::Watchdog,
+                AlertKind::BalanceChart,
+                AlertKind::Rebalancing,
+                AlertKind::Latency,
+            ]
+            .into_iter()
+            .map(|kind| AlertRouteConfig {
+                kind,
+                envs: Vec::new(),
+                channels: vec![channel.clone()],
+                min_abs_asset_change: Decimal::ZERO,
+                tokens: Vec::new(),
+            })
+            .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AlertRouteConfig {
+    pub kind: AlertKind,
+    // empty: every env
+    #[serde(default)]
+    pub envs: Vec<String>,
+    pub channels: Vec<String>,
+    // the alert must have at least one asset changed by this much
+    #[serde(default)]
+    pub min_abs_asset_change: Decimal,
+    // empty: every token
+    #[serde(default)]
+    pub tokens: Vec<String>,
+}
+
+#[derive(Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AlertKind {
+    #[serde(rename = "state-done")]
+    StateDone,
+    #[serde(rename = "balance-diff-1h")]
+    BalanceDiff1h,
+    #[serde(rename = "balance-diff-24h")]
+    BalanceDiff24h,
+    #[serde(rename = "balance-snapshot")]
+    BalanceSnapshot,
+    #[serde(rename = "errors")]
+    Error,
+    #[serde(rename = "pnl-daily")]
+    PnlDaily,
+    #[serde(rename = "anomaly")]
+    Anomaly,
+    #[serde(rename = "watchdog")]
+    Watchdog,
+    #[serde(rename = "balance-chart")]
+    BalanceChart,
+    #[serde(rename = "rebalancing")]
+    Rebalancing,
+    #[serde(rename = "latency")]
+    Latency,
 }
 
 impl Config {
@@ -1,81 +1,411 @@
 #![allow(dead_code)]
 
-
+mod admin;
+mod alertqueue;
+mod anomaly;
 mod balance_monitor;
 mod cexdexclient;
-mod slackclient;
+mod inspect;
+mod latency;
+mod logging;
+mod metrics;
+mod notifier;
+mod outage;
+mod pnl;
+mod pricing;
+mod rebalance;
+mod replay;
+mod routing;
+mod scheduler;
+mod store;
+mod supervisor;
+#[cfg(test)]
+mod testutil;
+mod timeline;
+mod watchdog;
 
 use std::{
     collections::{HashMap, HashSet},
-    thread,
+    sync::Arc,
     time::Duration,
 };
 
 use crate::cexdexclient::client::*;
-use cex_dex_monitor::{CexDexConfig, Config, URL};
+use crate::slackclient::message::Message;
+use cex_dex_monitor::{slackclient, AlertKind, CexDexConfig, Config, URL};
+use rust_decimal::prelude::ToPrimitive;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+const CONFIG_PATH: &str = "secret.yaml";
 
-#[tokio::main]
-async fn main() {
-    let cfg = Config::from_yaml("secret.yaml".to_string()).unwrap();
+fn main() {
+    logging::init();
 
-    let mut sl_client = slackclient::client::Client::new();
-    match cfg.slack_client_config.webhooks {
-        None => panic!("empty slack webhooks"),
-        Some(wh) => {
-            if wh.is_empty() {
-                panic!("empty slack webhooks")
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|a| a.as_str()) {
+        Some("replay") => {
+            if let Err(e) = replay_cmd(&args[1..]) {
+                error!(error = %e, "replay failed");
+                std::process::exit(1);
+            }
+        }
+        Some("chart") => {
+            if let Err(e) = chart_cmd(&args[1..]) {
+                error!(error = %e, "chart failed");
+                std::process::exit(1);
             }
-            for w in wh {
-                sl_client.add_webhook(w.channel, w.webhook);
+        }
+        Some(cmd @ ("state" | "states")) => {
+            if let Err(e) = inspect_cmd(cmd, &args[1..]) {
+                error!(error = %e, "{} failed", cmd);
+                std::process::exit(1);
             }
         }
+        _ => tokio::runtime::Runtime::new().unwrap().block_on(run()),
     }
+}
 
-    for v in cfg.cex_dex_config {
-        for url in v.urls.iter() {
-            let env = v.env.clone();
-            let slack = sl_client.clone();
-            let url = url.clone();
-            tokio::spawn(async move {
-                monitor(env, url, slack).await;
-            });
+// replay <recording> [--config <path>] [--out <path>], alerts go to stdout without --out
+fn replay_cmd(args: &[String]) -> anyhow::Result<()> {
+    let mut recording = None;
+    let mut config = CONFIG_PATH.to_string();
+    let mut out: Box<dyn std::io::Write + Send> = Box::new(std::io::stdout());
+    let mut args = args.iter();
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--config" => {
+                config = args
+                    .next()
+                    .ok_or_else(|| anyhow::format_err!("missing --config value"))?
+                    .clone()
+            }
+            "--out" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow::format_err!("missing --out value"))?;
+                out = Box::new(std::fs::File::create(path)?);
+            }
+            _ => recording = Some(a.clone()),
         }
+    }
+    let recording = recording.ok_or_else(|| {
+        anyhow::format_err!("usage: replay <recording> [--config <path>] [--out <path>]")
+    })?;
+    let cfg = Config::from_yaml(config).map_err(|e| anyhow::format_err!("{}", e))?;
 
-        if v.env.ne("prod") {
-            continue;
+    replay::run(&recording, cfg, out)
+}
+
+// chart <env> <asset> [--from <date>] [--to <date>] [--format csv|svg|png] [--out <path>]
+// [--config <path>], the last 7 days by default, csv and svg go to stdout without --out
+fn chart_cmd(args: &[String]) -> anyhow::Result<()> {
+    let usage = "usage: chart <env> <asset> [--from <yyyy-mm-dd>] [--to <yyyy-mm-dd>] \
+                 [--format csv|svg|png] [--out <path>] [--config <path>]";
+    let mut positional = Vec::new();
+    let mut config = CONFIG_PATH.to_string();
+    let mut from = None;
+    let mut to = None;
+    let mut format = "csv".to_string();
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(a) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| anyhow::format_err!("missing {} value", a))
+        };
+        match a.as_str() {
+            "--config" => config = value()?,
+            "--from" => from = Some(value()?.parse::<chrono::NaiveDate>()?),
+            "--to" => to = Some(value()?.parse::<chrono::NaiveDate>()?),
+            "--format" => format = value()?,
+            "--out" => out = Some(value()?),
+            _ => positional.push(a.clone()),
         }
-        let config = v.clone();
-        let slack = sl_client.clone();
+    }
+    let [env, asset] = positional.as_slice() else {
+        anyhow::bail!(usage);
+    };
+    let cfg = Config::from_yaml(config).map_err(|e| anyhow::format_err!("{}", e))?;
+
+    // --to is included
+    let to = to.unwrap_or_else(|| chrono::Utc::now().date_naive()) + chrono::Duration::days(1);
+    let from = from.unwrap_or(to - chrono::Duration::days(7));
+    let start = |d: chrono::NaiveDate| d.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let timeline = timeline::from_config(&cfg.timeline)?
+        .ok_or_else(|| anyhow::format_err!("no timeline path in the config"))?;
+    let series = timeline.series(env, asset, start(from), start(to))?;
+
+    let title = format!("{} {}", env, asset);
+    match (format.as_str(), out) {
+        ("csv", None) => timeline::chart::write_csv(std::io::stdout(), &series),
+        ("csv", Some(path)) => timeline::chart::write_csv(std::fs::File::create(path)?, &series),
+        ("svg", out) => {
+            timeline::chart::load_font(&cfg.timeline.font_path)?;
+            let svg = timeline::chart::svg(&title, &series)?;
+            match out {
+                None => print!("{}", svg),
+                Some(path) => std::fs::write(path, svg)?,
+            }
+            Ok(())
+        }
+        ("png", Some(path)) => {
+            timeline::chart::load_font(&cfg.timeline.font_path)?;
+            timeline::chart::png(&path, &title, &series)
+        }
+        ("png", None) => anyhow::bail!("png needs --out"),
+        _ => anyhow::bail!(usage),
+    }
+}
+
+// state <env> <state_id> [--size <n>] [--json] [--config <path>]
+// states <env> [--since <time|date>] [--size <n>] [--json] [--config <path>]
+// states are searched in the last n (100) done and running states of every url of the env,
+// --since is 24h ago by default
+fn inspect_cmd(cmd: &str, args: &[String]) -> anyhow::Result<()> {
+    let usage = match cmd {
+        "state" => "usage: state <env> <state_id> [--size <n>] [--json] [--config <path>]",
+        _ => "usage: states <env> [--since <rfc3339|yyyy-mm-dd>] [--size <n>] [--json] [--config <path>]",
+    };
+    let mut positional = Vec::new();
+    let mut config = CONFIG_PATH.to_string();
+    let mut size = 100;
+    let mut json = false;
+    let mut since = chrono::Utc::now() - chrono::Duration::hours(24);
+    let mut args = args.iter();
+    while let Some(a) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| anyhow::format_err!("missing {} value", a))
+        };
+        match a.as_str() {
+            "--config" => config = value()?,
+            "--size" => size = value()?.parse()?,
+            "--json" => json = true,
+            "--since" => {
+                let v = value()?;
+                since = match chrono::DateTime::parse_from_rfc3339(&v) {
+                    Ok(t) => t.to_utc(),
+                    Err(_) => v
+                        .parse::<chrono::NaiveDate>()?
+                        .and_hms_opt(0, 0, 0)
+                        .unwrap()
+                        .and_utc(),
+                };
+            }
+            _ => positional.push(a.clone()),
+        }
+    }
+    let cfg = Config::from_yaml(config).map_err(|e| anyhow::format_err!("{}", e))?;
+
+    match (cmd, positional.as_slice()) {
+        ("state", [env, state_id]) => {
+            inspect::state(&cfg, env, state_id, size, json, std::io::stdout())
+        }
+        ("states", [env]) => inspect::states(&cfg, env, since, size, json, std::io::stdout()),
+        _ => anyhow::bail!(usage),
+    }
+}
+
+async fn run() {
+    let cfg = Arc::new(Config::from_yaml(CONFIG_PATH.to_string()).unwrap());
+    let store = store::from_config(&cfg.state_store).unwrap();
+
+    let notifiers = notifier::Notifiers::from_config(&cfg).unwrap();
+    let queue_stop = CancellationToken::new();
+    let (queue, queue_worker) =
+        alertqueue::Queue::start(&cfg.alert_queue, Arc::new(notifiers), queue_stop.clone());
+    let router = routing::Router::new(&cfg.alert_routing, Arc::new(queue.clone()));
+
+    if let Some(m) = &cfg.metrics {
+        let addr = m.listen_addr.parse().expect("invalid metrics listen_addr");
         tokio::spawn(async move {
-            monitor_balances(config, slack).await;
+            if let Err(e) = metrics::serve(addr).await {
+                error!(error = %e, "metrics server failed");
+            }
         });
     }
 
-    // block for ctrl + C
+    if let Some(a) = &cfg.admin {
+        let addr = a.listen_addr.parse().expect("invalid admin listen_addr");
+        let token = a.token.clone();
+        let silences = router.silences();
+        tokio::spawn(async move {
+            if let Err(e) = admin::serve(addr, token, silences).await {
+                error!(error = %e, "admin server failed");
+            }
+        });
+    }
+
+    let shutdown = CancellationToken::new();
+
+    let prices = pricing::from_config(&cfg.pricing);
+    let ledger = Arc::new(pnl::Ledger::new(&cfg.pnl));
+    let timeline = timeline::from_config(&cfg.timeline).unwrap();
+    let mut supervisor = supervisor::Supervisor::new(
+        router.clone(),
+        store,
+        ledger.clone(),
+        prices,
+        timeline.clone(),
+        shutdown.clone(),
+    );
+    supervisor.spawn(pnl::run_daily_summary(
+        ledger,
+        router.clone(),
+        shutdown.clone(),
+    ));
+    if let Some(t) = timeline.filter(|_| cfg.timeline.weekly_chart) {
+        supervisor.spawn(timeline::run_weekly_chart(
+            t,
+            cfg.timeline.clone(),
+            router.clone(),
+            shutdown.clone(),
+        ));
+    }
+    supervisor.apply(cfg.clone()).await;
+
+    // reload the config file on change until SIGINT/SIGTERM,
+    // state_store, metrics, admin, pricing, pnl, timeline and alert_queue only change on restart
+    let mut cfg = cfg;
+    let mut content = std::fs::read_to_string(CONFIG_PATH).unwrap_or_default();
+    let signal = scheduler::shutdown_signal();
+    tokio::pin!(signal);
     loop {
-        thread::sleep(Duration::from_secs(10))
+        let interval = scheduler::Interval::from(&cfg.scheduler.config_poll);
+        tokio::select! {
+            _ = &mut signal => break,
+            _ = tokio::time::sleep(interval.next_delay()) => {}
+            // restarts the monitor tasks that exit on their own meanwhile
+            _ = supervisor.supervise() => {}
+        }
+
+        let new_content = match std::fs::read_to_string(CONFIG_PATH) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!(path = CONFIG_PATH, error = %e, "read config failed");
+                continue;
+            }
+        };
+        if new_content == content {
+            continue;
+        }
+        content = new_content;
+
+        match reload(&content, &router, &queue) {
+            Ok(new_cfg) => {
+                info!(path = CONFIG_PATH, "config reloaded");
+                cfg = new_cfg;
+                supervisor.apply(cfg.clone()).await;
+            }
+            Err(e) => warn!(
+                path = CONFIG_PATH,
+                error = %e,
+                "reload config failed, keeping the running config"
+            ),
+        }
+    }
+
+    // let the monitors finish what they are sending
+    let grace = Duration::from_secs(cfg.scheduler.shutdown_grace_secs);
+    if !supervisor.stop(grace).await {
+        warn!(?grace, "monitors did not stop in time, exiting");
+        return;
+    }
+    info!("all monitors stopped");
+
+    // then send what is still queued
+    queue_stop.cancel();
+    if tokio::time::timeout(grace, queue_worker).await.is_err() {
+        warn!(?grace, "alert queue not flushed in time, exiting");
     }
 }
 
-async fn monitor(env: String, url: URL, sl_client: slackclient::client::Client) {
-    let mut done_states: HashSet<String> = HashSet::new();
+// parses a changed config and swaps the routes and notifiers of the router,
+// nothing is changed on error
+fn reload(
+    content: &str,
+    router: &routing::Router,
+    queue: &alertqueue::Queue,
+) -> anyhow::Result<Arc<Config>> {
+    let cfg: Config = serde_yaml::from_str(content)?;
+    let notifiers = notifier::Notifiers::from_config(&cfg)?;
+    router.reload(
+        &cfg.alert_routing,
+        Arc::new(queue.with_notifier(Arc::new(notifiers))),
+    )?;
+    Ok(Arc::new(cfg))
+}
+
+#[tracing::instrument(skip_all, fields(env = %env, base_url = %url.redacted()))]
+async fn monitor(
+    env: String,
+    url: URL,
+    cfg: Arc<Config>,
+    router: routing::Router,
+    store: Arc<dyn store::Store>,
+    ledger: Arc<pnl::Ledger>,
+    shutdown: CancellationToken,
+) {
+    let interval = scheduler::Interval::from(&cfg.scheduler.state_poll);
+    let scope = store::seen_states_scope(&env, &url.base_url);
+    let mut done_states: HashSet<String> = match store.load_seen_states(&scope) {
+        Ok(Some(s)) => s,
+        Ok(None) => HashSet::new(),
+        Err(e) => {
+            warn!(error = %e, "load seen states failed");
+            HashSet::new()
+        }
+    };
     let mut_done_states = &mut done_states;
+    info!(count = mut_done_states.len(), "loaded seen states");
 
-    let cd_client = CexDexClient::new(url.base_url.clone(), url.user.clone(), url.pass.clone());
+    let cd_client = CexDexClient::new(
+        url.base_url.clone(),
+        url.user.clone(),
+        url.pass.clone(),
+        &cfg.cex_dex_client,
+    );
+    let mut outages = outage::OutageTracker::new(Duration::from_secs(
+        cfg.cex_dex_client.outage_alert_after_secs,
+    ));
 
-    println!("starting loop..., env: {}", env);
+    info!("monitor started");
     loop {
-        thread::sleep(Duration::from_secs(
-            10 + (chrono::Utc::now().timestamp() % 10) as u64,
-        ));
-        let now = chrono::Utc::now().to_rfc3339();
+        if !scheduler::sleep(&interval, &shutdown).await {
+            info!("monitor stopped");
+            return;
+        }
 
         let states = cd_client.get_filled_done_states().await;
+        if let Some(o) = outages.record(states.is_ok(), scheduler::now()) {
+            let what = format!("get states of {}", url.redacted());
+            if let Err(e) = router
+                .send(
+                    &routing::Alert::new(AlertKind::Error, &env),
+                    o.message(
+                        &env,
+                        &what,
+                        states
+                            .as_ref()
+                            .err()
+                            .map(|e| format!("[{}] {}", e.kind(), e)),
+                    ),
+                )
+                .await
+            {
+                warn!(error = %e, "send error alert failed");
+            }
+        }
         if let Err(e) = states {
-            println!("{} get states error {}", now, e);
+            warn!(error = %e, "get states failed");
+            metrics::get().inc_fetch_error(&env, metrics::GET_FILLED_DONE_STATES, e.kind());
             continue;
         }
         let states = states.unwrap();
+        metrics::get().set_last_success(&env, metrics::GET_FILLED_DONE_STATES);
 
         // insert first time run
         if mut_done_states.is_empty() {
@@ -88,14 +418,15 @@ async fn monitor(env: String, url: URL, sl_client: slackclient::client::Client)
                 // insert states
                 mut_done_states.insert(state.state_id.clone());
             }
-            println!(
-                "{} first run: inserted {} states",
-                now,
-                mut_done_states.len()
-            );
+            info!(count = mut_done_states.len(), "first run, inserted states");
+            if let Err(e) = store.save_seen_states(&scope, mut_done_states) {
+                warn!(error = %e, "save seen states failed");
+            }
             continue;
         }
 
+        let mut changed = false;
+
         for i in 0..states.data.len() {
             let state = &states.data[i];
             // skips empty state
@@ -107,22 +438,9 @@ async fn monitor(env: String, url: URL, sl_client: slackclient::client::Client)
             if !mut_done_states.insert(state.state_id.clone()) {
                 continue;
             }
+            changed = true;
 
-            // notify new state
-            if let Err(e) = sl_client
-                .send_message(
-                    String::from("alert-virtual-taker-1"),
-                    build_state_done_message(state, &env),
-                )
-                .await
-            {
-                println!("{} send slack client error {}", now, e);
-                continue;
-            }
-            println!(
-                "{} env {}, send update of state id {}",
-                now, env, state.state_id
-            );
+            notify_state(state, &env, &cfg, &router, &ledger).await;
         }
 
         // remove old done states - is not in states
@@ -143,21 +461,93 @@ async fn monitor(env: String, url: URL, sl_client: slackclient::client::Client)
 
         for id in remove_state_ids {
             mut_done_states.remove(&id);
-            println!("{} env {}, removed state id {}", now, env, id);
+            changed = true;
+            debug!(state_id = %id, "removed state");
+        }
+
+        if changed {
+            if let Err(e) = store.save_seen_states(&scope, mut_done_states) {
+                warn!(error = %e, "save seen states failed");
+            }
         }
     }
 }
 
-fn build_state_done_message(state: &cexdexclient::dto::StateData, env: &String) -> String {
+// state done alert, pnl and anomalies of a newly done state, a failed alert
+// does not skip the others: the state is not fetched again
+#[tracing::instrument(skip_all, fields(state_id = %state.state_id))]
+async fn notify_state(
+    state: &cexdexclient::dto::StateData,
+    env: &String,
+    cfg: &Config,
+    router: &routing::Router,
+    ledger: &pnl::Ledger,
+) {
+    let alert =
+        routing::Alert::new(AlertKind::StateDone, env).with_assets(state.asset_changes_vec());
+    let explorer_tx_url = cfg
+        .block_explorers
+        .get(&state.dex_chain)
+        .map(|u| u.as_str());
+    let state_pnl = pnl::compute(state, env, &cfg.pnl);
+    if let Err(e) = ledger.append(&state_pnl) {
+        warn!(error = %e, "append pnl failed");
+    }
+    match router
+        .send(
+            &alert,
+            build_state_done_message(state, env, explorer_tx_url, &state_pnl),
+        )
+        .await
+    {
+        Ok(()) => {
+            metrics::get().inc_states_notified(env);
+            info!("sent state done");
+        }
+        Err(e) => warn!(error = %e, "send state done alert failed"),
+    }
+    let state_latency =
+        latency::StateLatency::new(state, state_pnl.gas.to_f64().unwrap_or_default());
+    let events = latency::get().record(env, &state_latency, scheduler::now(), &cfg.latency);
+    admin::get().add_state(env, state, state_latency);
+    for e in events {
+        let msg = latency::build_message(env, &e, cfg.latency.window_secs);
+        if let Err(e) = router
+            .send(&routing::Alert::new(AlertKind::Latency, env), msg)
+            .await
+        {
+            warn!(error = %e, "send latency alert failed");
+        }
+    }
+
+    let findings = anomaly::check(state, &cfg.pnl.quote_asset, &cfg.anomaly);
+    if !findings.is_empty() {
+        let alert =
+            routing::Alert::new(AlertKind::Anomaly, env).with_assets(state.asset_changes_vec());
+        let msg = anomaly::build_message(state, env, &findings, &cfg.anomaly.mention);
+        if let Err(e) = router.send(&alert, msg).await {
+            warn!(error = %e, "send anomaly alert failed");
+        }
+    }
+}
+
+fn build_state_done_message(
+    state: &cexdexclient::dto::StateData,
+    env: &String,
+    explorer_tx_url: Option<&str>,
+    state_pnl: &pnl::StatePnl,
+) -> Message {
     let p2_dex_token_filled = state.p2_sum_token_filled(&state.token);
     let p2_dex_stable_filled = state.p2_sum_token_filled(&String::from("USDT")); // now using usdt only
-    let p2_dex_price = if p2_dex_token_filled == 0 as f64 {
+    let p2_dex_price = if p2_dex_token_filled.is_zero() {
         0.0
     } else {
-        p2_dex_stable_filled / p2_dex_token_filled
+        (p2_dex_stable_filled / p2_dex_token_filled)
+            .to_f64()
+            .unwrap_or_default()
     };
 
-    format!(
+    let fallback = format!(
         "*****
 *STATE DONE*
 
@@ -182,6 +572,7 @@ P2 PRICE: {}
 {}
 ASSET CHANGES:
 {}
+PNL ({}): gross {}, cex fee {}, gas {}, net {}
 *****",
         env,
         state.state_id,
@@ -200,26 +591,177 @@ ASSET CHANGES:
         p2_dex_price,
         state.p2_summary_txs(),
         state.asset_changes(),
-    )
+        state_pnl.quote,
+        state_pnl.gross,
+        state_pnl.cex_fee,
+        state_pnl.gas,
+        state_pnl.net,
+    );
+
+    let mut asset_changes = state.asset_changes_vec();
+    asset_changes.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    Message::builder(fallback)
+        .header("STATE DONE")
+        .fields(vec![
+            ("ENV", env.clone()),
+            ("STATE_ID", state.state_id.clone()),
+            ("SIDE", state.side.clone()),
+            ("TOKEN", state.token.clone()),
+        ])
+        .divider()
+        .fields(vec![
+            (
+                "P1 FILLED ORDERS",
+                state.count_p1_filled_orders().to_string(),
+            ),
+            ("P1 BASE FILLED", state.p1_sum_base_filled().to_string()),
+            ("P1 QUOTE FILLED", state.p1_sum_quote_filled().to_string()),
+            (
+                "P1 PRICE",
+                state.get_cex_price(&state.p1_cex_orders).to_string(),
+            ),
+        ])
+        .fields(vec![
+            (
+                "P2 FILLED ORDERS",
+                state.count_p2_filled_orders().to_string(),
+            ),
+            ("P2 BASE FILLED", state.p2_sum_base_filled().to_string()),
+            ("P2 QUOTE FILLED", state.p2_sum_quote_filled().to_string()),
+            (
+                "P2 PRICE",
+                state.get_cex_price(&state.p2_cex_orders).to_string(),
+            ),
+        ])
+        .fields(vec![
+            ("P2 CREATED TXs", state.p2_count_created_txs().to_string()),
+            ("P2 TOKEN FILLED", p2_dex_token_filled.to_string()),
+            ("P2 STABLE FILLED", p2_dex_stable_filled.to_string()),
+            ("P2 DEX PRICE", p2_dex_price.to_string()),
+        ])
+        .tx_links("P2 TXs", explorer_tx_url, &state.p2_txs_vec())
+        .diff_rows("ASSET CHANGES", &asset_changes)
+        .fields(vec![
+            (
+                "PNL GROSS",
+                format!("{} {}", state_pnl.gross, state_pnl.quote),
+            ),
+            (
+                "CEX FEE",
+                format!("{} {}", state_pnl.cex_fee, state_pnl.quote),
+            ),
+            ("GAS", format!("{} {}", state_pnl.gas, state_pnl.quote)),
+            ("PNL NET", format!("{} {}", state_pnl.net, state_pnl.quote)),
+        ])
+        .build()
 }
 
-async fn monitor_balances(cex_dex_cfg: CexDexConfig, sl_client: slackclient::client::Client) {
-    println!("monitor_balances started");
+#[tracing::instrument(skip_all, fields(env = %cex_dex_cfg.env))]
+async fn monitor_balances(
+    cex_dex_cfg: CexDexConfig,
+    cfg: Arc<Config>,
+    router: routing::Router,
+    store: Arc<dyn store::Store>,
+    prices: Arc<dyn pricing::PriceProvider>,
+    timeline: Option<Arc<timeline::Timeline>>,
+    shutdown: CancellationToken,
+) {
+    info!("balance monitor started");
 
-    let mut cex_dex_clients = Vec::<CexDexClient>::new();
+    let mut cex_dex_clients = Vec::<balance_monitor::VenueClient>::new();
     for url in cex_dex_cfg.urls.iter() {
-        let cd_client = CexDexClient::new(url.base_url.clone(), url.user.clone(), url.pass.clone());
-        cex_dex_clients.push(cd_client);
+        let cd_client = CexDexClient::new(
+            url.base_url.clone(),
+            url.user.clone(),
+            url.pass.clone(),
+            &cfg.cex_dex_client,
+        );
+        cex_dex_clients.push(balance_monitor::VenueClient {
+            client: cd_client,
+            cexes: url.cexes.clone(),
+            dex_chains: url.dex_chains.clone(),
+        });
     }
 
-    let epsilon = 0.00001;
+    let source =
+        balance_monitor::CexDexBalanceSource::new(cex_dex_cfg.env.clone(), cex_dex_clients);
 
     let mut s = balance_monitor::Service::new(
         cex_dex_cfg.env.clone(),
-        epsilon,
-        cex_dex_clients,
-        &sl_client,
-    );
+        &cfg.assets,
+        Box::new(source),
+        &router,
+        store,
+        &cfg.scheduler,
+        &cfg.cex_dex_client,
+    )
+    .with_prices(prices);
+    if let Some(t) = timeline {
+        s = s.with_timeline(t);
+    }
+
+    s.monitor_balance(&shutdown).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use cex_dex_monitor::{AlertKind, Config};
 
-    s.monitor_balance().await;
+    use super::notify_state;
+    use crate::{
+        notifier::Notifier, pnl, routing::Router, slackclient::message::Message, testutil,
+    };
+
+    // fails the state done alerts, records the others
+    #[derive(Default)]
+    struct StateDoneDown {
+        sent: Mutex<Vec<AlertKind>>,
+    }
+
+    #[async_trait]
+    impl Notifier for StateDoneDown {
+        fn has_channel(&self, _: &str) -> bool {
+            true
+        }
+
+        async fn send(&self, _: &str, kind: AlertKind, _: &Message) -> anyhow::Result<()> {
+            if kind == AlertKind::StateDone {
+                return Err(anyhow::format_err!("webhook down"));
+            }
+            self.sent.lock().unwrap().push(kind);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_state_done_alert_still_sends_anomalies() {
+        let dir =
+            std::env::temp_dir().join(format!("cex-dex-monitor-notify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut cfg: Config =
+            serde_yaml::from_str("{cex_dex_config: [], slack_client_config: {}}").unwrap();
+        cfg.pnl.csv_path = dir.join("states.csv").to_string_lossy().to_string();
+        cfg.pnl.daily_csv_path = dir.join("daily.csv").to_string_lossy().to_string();
+
+        let notifier = Arc::new(StateDoneDown::default());
+        let router = Router::new(&cfg.alert_routing, notifier.clone());
+        let mut state = testutil::state();
+        state.p2_dex_txs.as_mut().unwrap()[0].status = "FAILED".to_string();
+
+        notify_state(
+            &state,
+            &"prod".to_string(),
+            &cfg,
+            &router,
+            &pnl::Ledger::new(&cfg.pnl),
+        )
+        .await;
+
+        assert!(notifier.sent.lock().unwrap().contains(&AlertKind::Anomaly));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
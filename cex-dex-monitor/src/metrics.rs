@@ -0,0 +1,208 @@
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Mutex, OnceLock},
+};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use prometheus::{Encoder, GaugeVec, IntCounterVec, Opts, Registry, TextEncoder};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use tracing::info;
+
+// endpoint labels, named after the CexDexClient methods
+pub const GET_FILLED_DONE_STATES: &str = "get_filled_done_states";
+pub const GET_RUNNING_STATES: &str = "get_running_states";
+pub const GET_CEX_BALANCE: &str = "get_cex_balanace";
+pub const GET_DEX_BALANCE: &str = "get_dex_balanace";
+
+pub struct Metrics {
+    registry: Registry,
+    balance: GaugeVec,
+    states_notified: IntCounterVec,
+    fetch_errors: IntCounterVec,
+    rebalancing_skips: IntCounterVec,
+    last_success: GaugeVec,
+    // assets exported per env, to drop the ones no longer returned
+    balance_assets: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+pub fn get() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+impl Metrics {
+    fn new() -> Metrics {
+        let registry = Registry::new_custom(Some(String::from("cex_dex")), None).unwrap();
+
+        let balance = GaugeVec::new(
+            Opts::new("balance", "aggregated cex and dex balance per asset"),
+            &["env", "asset"],
+        )
+        .unwrap();
+        let states_notified = IntCounterVec::new(
+            Opts::new("states_notified_total", "done states sent to slack"),
+            &["env"],
+        )
+        .unwrap();
+        let fetch_errors = IntCounterVec::new(
+            Opts::new("fetch_errors_total", "failed requests to the cex dex api"),
+            &["env", "endpoint", "kind"],
+        )
+        .unwrap();
+        let rebalancing_skips = IntCounterVec::new(
+            Opts::new(
+                "rebalancing_skips_total",
+                "balance fetches skipped because of rebalancing",
+            ),
+            &["env", "venue"],
+        )
+        .unwrap();
+        let last_success = GaugeVec::new(
+            Opts::new(
+                "last_success_timestamp_seconds",
+                "unix time of the last successful request",
+            ),
+            &["env", "endpoint"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(balance.clone())).unwrap();
+        registry
+            .register(Box::new(states_notified.clone()))
+            .unwrap();
+        registry.register(Box::new(fetch_errors.clone())).unwrap();
+        registry
+            .register(Box::new(rebalancing_skips.clone()))
+            .unwrap();
+        registry.register(Box::new(last_success.clone())).unwrap();
+
+        Metrics {
+            registry,
+            balance,
+            states_notified,
+            fetch_errors,
+            rebalancing_skips,
+            last_success,
+            balance_assets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn set_balances(&self, env: &str, balances: &HashMap<String, Decimal>) {
+        let mut exported = self.balance_assets.lock().unwrap();
+        let assets = exported.entry(env.to_string()).or_default();
+        for asset in assets.iter() {
+            if !balances.contains_key(asset) {
+                let _ = self.balance.remove_label_values(&[env, asset]);
+            }
+        }
+        assets.clear();
+
+        for (asset, amount) in balances.iter() {
+            self.balance
+                .with_label_values(&[env, asset])
+                .set(amount.to_f64().unwrap_or_default());
+            assets.insert(asset.clone());
+        }
+    }
+
+    pub fn inc_states_notified(&self, env: &str) {
+        self.states_notified.with_label_values(&[env]).inc();
+    }
+
+    // kind: ApiError::kind
+    pub fn inc_fetch_error(&self, env: &str, endpoint: &str, kind: &str) {
+        self.fetch_errors
+            .with_label_values(&[env, endpoint, kind])
+            .inc();
+    }
+
+    pub fn inc_rebalancing_skip(&self, env: &str, venue: &str) {
+        self.rebalancing_skips
+            .with_label_values(&[env, venue])
+            .inc();
+    }
+
+    pub fn set_last_success(&self, env: &str, endpoint: &str) {
+        self.last_success
+            .with_label_values(&[env, endpoint])
+            .set(chrono::Utc::now().timestamp() as f64);
+    }
+
+    pub fn encode(&self) -> anyhow::Result<String> {
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf)?;
+        Ok(String::from_utf8(buf)?)
+    }
+}
+
+// serve GET /metrics until the process exits
+pub async fn serve(addr: SocketAddr) -> anyhow::Result<()> {
+    let make_svc = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+
+    info!(%addr, "metrics server listening");
+    hyper::Server::try_bind(&addr)?.serve(make_svc).await?;
+
+    Ok(())
+}
+
+async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        return Ok(response(StatusCode::NOT_FOUND, String::from("not found")));
+    }
+
+    match get().encode() {
+        Ok(body) => Ok(response(StatusCode::OK, body)),
+        Err(e) => Ok(response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("encode metrics error {}", e),
+        )),
+    }
+}
+
+fn response(status: StatusCode, body: String) -> Response<Body> {
+    let mut resp = Response::new(Body::from(body));
+    *resp.status_mut() = status;
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rust_decimal_macros::dec;
+
+    use super::get;
+
+    #[test]
+    fn stale_balance_assets_are_dropped() {
+        let m = get();
+        m.set_balances(
+            "metrics-test",
+            &HashMap::from([
+                ("KNC".to_string(), dec!(1.5)),
+                ("USDT".to_string(), dec!(10)),
+            ]),
+        );
+        m.inc_fetch_error("metrics-test", super::GET_DEX_BALANCE, "api_code");
+
+        let text = m.encode().unwrap();
+        assert!(text.contains(r#"cex_dex_balance{asset="KNC",env="metrics-test"} 1.5"#));
+        assert!(text.contains(
+            r#"cex_dex_fetch_errors_total{endpoint="get_dex_balanace",env="metrics-test",kind="api_code"} 1"#
+        ));
+
+        m.set_balances(
+            "metrics-test",
+            &HashMap::from([("USDT".to_string(), dec!(11))]),
+        );
+        let text = m.encode().unwrap();
+        assert!(!text.contains(r#"asset="KNC",env="metrics-test""#));
+        assert!(text.contains(r#"cex_dex_balance{asset="USDT",env="metrics-test"} 11"#));
+    }
+}
@@ -0,0 +1,112 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use cex_dex_monitor::{AlertKind, Config, NotifierKind};
+
+use crate::slackclient::{self, message::Message};
+
+pub mod smtp;
+pub mod telegram;
+mod test;
+pub mod webhook;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Notifier delivers a message to a named channel
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    fn has_channel(&self, channel: &str) -> bool;
+    async fn send(&self, channel: &str, kind: AlertKind, msg: &Message) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+impl Notifier for slackclient::client::Client {
+    fn has_channel(&self, channel: &str) -> bool {
+        slackclient::client::Client::has_channel(self, channel)
+    }
+
+    async fn send(&self, channel: &str, _: AlertKind, msg: &Message) -> anyhow::Result<()> {
+        slackclient::client::Client::send(self, channel.to_string(), msg)
+            .await
+            .map_err(|e| anyhow::format_err!("{}", e))
+    }
+}
+
+// Notifiers sends each channel through the notifier configured for it
+#[derive(Default)]
+pub struct Notifiers {
+    channels: HashMap<String, Arc<dyn Notifier>>,
+}
+
+impl Notifiers {
+    // every slack webhook and every configured notifier
+    pub fn from_config(cfg: &Config) -> anyhow::Result<Notifiers> {
+        let mut n = Notifiers::default();
+
+        let webhooks = cfg.slack_client_config.webhooks.clone().unwrap_or_default();
+        let mut sl_client = slackclient::client::Client::new();
+        for w in webhooks.iter() {
+            sl_client.add_webhook(w.channel.clone(), w.webhook.clone());
+        }
+        let sl_client = Arc::new(sl_client);
+        for w in webhooks.iter() {
+            n.add(&w.channel, sl_client.clone())?;
+        }
+
+        for c in cfg.notifiers.iter() {
+            let notifier: Arc<dyn Notifier> = match &c.kind {
+                NotifierKind::Telegram {
+                    bot_token,
+                    chat_id,
+                    api_url,
+                } => Arc::new(telegram::TelegramNotifier::new(api_url, bot_token, chat_id)),
+                NotifierKind::Webhook { url, headers } => {
+                    Arc::new(webhook::WebhookNotifier::new(url, headers)?)
+                }
+                NotifierKind::Smtp {
+                    host,
+                    port,
+                    from,
+                    to,
+                } => Arc::new(smtp::SmtpNotifier::new(host, *port, from, to)?),
+            };
+            n.add(&c.name, notifier)?;
+        }
+
+        if n.channels.is_empty() {
+            return Err(anyhow::format_err!("no slack webhooks nor notifiers"));
+        }
+        Ok(n)
+    }
+
+    pub fn add(&mut self, channel: &str, notifier: Arc<dyn Notifier>) -> anyhow::Result<()> {
+        if self.channels.contains_key(channel) {
+            return Err(anyhow::format_err!("duplicated channel {}", channel));
+        }
+        self.channels.insert(channel.to_string(), notifier);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for Notifiers {
+    fn has_channel(&self, channel: &str) -> bool {
+        self.channels.contains_key(channel)
+    }
+
+    async fn send(&self, channel: &str, kind: AlertKind, msg: &Message) -> anyhow::Result<()> {
+        match self.channels.get(channel) {
+            Some(n) => n.send(channel, kind, msg).await,
+            None => Err(anyhow::format_err!("channel {} not found", channel)),
+        }
+    }
+}
+
+// first line of the text that is not only decoration, "*STATE DONE*" -> "STATE DONE"
+pub fn title(text: &str) -> String {
+    text.lines()
+        .map(|l| l.trim().trim_matches('*').trim())
+        .find(|l| !l.is_empty())
+        .unwrap_or_default()
+        .to_string()
+}
@@ -0,0 +1,57 @@
+use async_trait::async_trait;
+use cex_dex_monitor::AlertKind;
+use lettre::{
+    message::Mailbox, AsyncSmtpTransport, AsyncTransport, Message as Email, Tokio1Executor,
+};
+
+use super::{title, Notifier, TIMEOUT};
+use crate::slackclient::message::Message;
+
+// SmtpNotifier mails the plain text of the alerts through a local relay
+pub struct SmtpNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl SmtpNotifier {
+    pub fn new(host: &str, port: u16, from: &str, to: &[String]) -> anyhow::Result<SmtpNotifier> {
+        if to.is_empty() {
+            return Err(anyhow::format_err!("smtp notifier without recipients"));
+        }
+
+        Ok(SmtpNotifier {
+            // no tls: the relay is expected on the same host or network
+            transport: AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+                .port(port)
+                .timeout(Some(TIMEOUT))
+                .build(),
+            from: from.parse()?,
+            to: to
+                .iter()
+                .map(|t| t.parse())
+                .collect::<Result<Vec<Mailbox>, _>>()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for SmtpNotifier {
+    fn has_channel(&self, _: &str) -> bool {
+        true
+    }
+
+    async fn send(&self, channel: &str, _: AlertKind, msg: &Message) -> anyhow::Result<()> {
+        let mut email = Email::builder().from(self.from.clone()).subject(format!(
+            "[{}] {}",
+            channel,
+            title(&msg.text)
+        ));
+        for to in self.to.iter() {
+            email = email.to(to.clone());
+        }
+        self.transport.send(email.body(msg.text.clone())?).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+use cex_dex_monitor::AlertKind;
+use serde::Serialize;
+
+use super::{Notifier, TIMEOUT};
+use crate::slackclient::message::Message;
+
+// longer texts are rejected by the bot api
+const MAX_TEXT_LEN: usize = 4096;
+
+// TelegramNotifier sends the plain text of the alerts to a chat through a bot
+pub struct TelegramNotifier {
+    client: reqwest::Client,
+    url: String,
+    chat_id: String,
+}
+
+#[derive(Serialize)]
+struct SendMessage<'a> {
+    chat_id: &'a str,
+    text: String,
+    disable_web_page_preview: bool,
+}
+
+impl TelegramNotifier {
+    pub fn new(api_url: &str, bot_token: &str, chat_id: &str) -> TelegramNotifier {
+        TelegramNotifier {
+            client: reqwest::Client::new(),
+            url: format!("{}/bot{}/sendMessage", api_url, bot_token),
+            chat_id: chat_id.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    fn has_channel(&self, _: &str) -> bool {
+        true
+    }
+
+    async fn send(&self, _: &str, _: AlertKind, msg: &Message) -> anyhow::Result<()> {
+        let body = SendMessage {
+            chat_id: &self.chat_id,
+            text: msg.text.chars().take(MAX_TEXT_LEN).collect(),
+            disable_web_page_preview: true,
+        };
+        // the url holds the bot token, it is left out of the errors
+        let resp = self
+            .client
+            .post(&self.url)
+            .json(&body)
+            .timeout(TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| anyhow::format_err!("telegram send error: {}", e.without_url()))?;
+        if !resp.status().is_success() {
+            return Err(anyhow::format_err!(
+                "telegram send error, status {}, body [{}]",
+                resp.status(),
+                resp.text().await.unwrap_or_default()
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use cex_dex_monitor::{AlertKind, Config};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use crate::{
+        notifier::{
+            smtp::SmtpNotifier, telegram::TelegramNotifier, webhook::WebhookNotifier, Notifier,
+            Notifiers,
+        },
+        slackclient::message::Message,
+        testutil::{ok, MockServer},
+    };
+
+    fn alert() -> Message {
+        Message::plain("*****\n*BALANCES*\n> ENV: prod\nUSDT: 10".to_string())
+    }
+
+    fn config(notifiers: &str) -> Config {
+        serde_yaml::from_str(&format!(
+            "
+cex_dex_config: []
+slack_client_config:
+  webhooks:
+    - {{channel: alerts, webhook: \"http://localhost/alerts\"}}
+notifiers:
+{}
+",
+            notifiers
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn channels_are_slack_webhooks_and_named_notifiers() {
+        let n = Notifiers::from_config(&config(
+            "
+  - {name: phone, kind: telegram, bot_token: t, chat_id: \"1\"}
+  - {name: mail, kind: smtp, host: localhost, from: a@b.c, to: [d@e.f]}
+",
+        ))
+        .unwrap();
+        assert!(n.has_channel("alerts"));
+        assert!(n.has_channel("phone"));
+        assert!(n.has_channel("mail"));
+        assert!(!n.has_channel("other"));
+
+        let clash = config("  - {name: alerts, kind: webhook, url: \"http://localhost\"}");
+        assert!(Notifiers::from_config(&clash).is_err());
+    }
+
+    #[tokio::test]
+    async fn telegram_sends_the_text_to_the_chat() {
+        let server = MockServer::start().await;
+        server.respond("/botT0KEN/sendMessage", vec![ok("{\"ok\":true}")]);
+        let n = TelegramNotifier::new(&server.url, "T0KEN", "-100");
+
+        n.send("phone", AlertKind::BalanceSnapshot, &alert())
+            .await
+            .unwrap();
+
+        let body: serde_json::Value =
+            serde_json::from_str(&server.requests("/botT0KEN/sendMessage")[0]).unwrap();
+        assert_eq!(body["chat_id"], "-100");
+        assert_eq!(body["text"], alert().text.as_str());
+    }
+
+    #[tokio::test]
+    async fn telegram_errors_hide_the_bot_token() {
+        let n = TelegramNotifier::new("http://127.0.0.1:1", "T0KEN", "-100");
+
+        let e = n
+            .send("phone", AlertKind::BalanceSnapshot, &alert())
+            .await
+            .unwrap_err();
+
+        assert!(!e.to_string().contains("T0KEN"), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn webhook_posts_the_alert_as_json() {
+        let server = MockServer::start().await;
+        server.respond("/hook", vec![ok("")]);
+        let n = WebhookNotifier::new(
+            &format!("{}/hook", server.url),
+            &HashMap::from([("x-token".to_string(), "secret".to_string())]),
+        )
+        .unwrap();
+
+        n.send("pager", AlertKind::BalanceDiff1h, &alert())
+            .await
+            .unwrap();
+
+        let body: serde_json::Value = serde_json::from_str(&server.requests("/hook")[0]).unwrap();
+        assert_eq!(body["channel"], "pager");
+        assert_eq!(body["kind"], "balance-diff-1h");
+        assert_eq!(body["text"], alert().text.as_str());
+    }
+
+    // accepts a single mail and keeps its DATA
+    async fn smtp_server() -> (u16, Arc<Mutex<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let data = Arc::new(Mutex::new(String::new()));
+        let mail = data.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (r, mut w) = stream.into_split();
+            let mut lines = BufReader::new(r).lines();
+            w.write_all(b"220 localhost\r\n").await.unwrap();
+            let mut in_data = false;
+            while let Ok(Some(line)) = lines.next_line().await {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        w.write_all(b"250 queued\r\n").await.unwrap();
+                    } else {
+                        mail.lock().unwrap().push_str(&format!("{}\n", line));
+                    }
+                    continue;
+                }
+                let reply: &[u8] = match line.split(' ').next().unwrap_or_default() {
+                    "DATA" => {
+                        in_data = true;
+                        b"354 go ahead\r\n"
+                    }
+                    "QUIT" => b"221 bye\r\n",
+                    _ => b"250 ok\r\n",
+                };
+                w.write_all(reply).await.unwrap();
+            }
+        });
+        (port, data)
+    }
+
+    #[tokio::test]
+    async fn smtp_mails_the_alert() {
+        let (port, data) = smtp_server().await;
+        let n = SmtpNotifier::new(
+            "127.0.0.1",
+            port,
+            "monitor@localhost",
+            &["oncall@localhost".to_string()],
+        )
+        .unwrap();
+
+        n.send("mail", AlertKind::BalanceSnapshot, &alert())
+            .await
+            .unwrap();
+
+        let data = data.lock().unwrap().clone();
+        assert!(data.contains("Subject: [mail] BALANCES\n"), "{}", data);
+        assert!(data.contains("To: oncall@localhost\n"), "{}", data);
+        assert!(data.contains("USDT: 10\n"), "{}", data);
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use cex_dex_monitor::AlertKind;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Serialize;
+
+use super::{Notifier, TIMEOUT};
+use crate::slackclient::message::Message;
+
+// WebhookNotifier posts every alert as json to an url
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+    headers: HeaderMap,
+}
+
+// the slack message (text, blocks, attachments) with the channel and kind of the alert
+#[derive(Serialize)]
+struct Payload<'a> {
+    channel: &'a str,
+    kind: AlertKind,
+    #[serde(flatten)]
+    msg: &'a Message,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str, headers: &HashMap<String, String>) -> anyhow::Result<WebhookNotifier> {
+        let mut h = HeaderMap::new();
+        for (k, v) in headers {
+            h.insert(HeaderName::try_from(k)?, HeaderValue::try_from(v)?);
+        }
+
+        Ok(WebhookNotifier {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            headers: h,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn has_channel(&self, _: &str) -> bool {
+        true
+    }
+
+    async fn send(&self, channel: &str, kind: AlertKind, msg: &Message) -> anyhow::Result<()> {
+        let resp = self
+            .client
+            .post(&self.url)
+            .headers(self.headers.clone())
+            .json(&Payload { channel, kind, msg })
+            .timeout(TIMEOUT)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(anyhow::format_err!(
+                "webhook {} response not success, status {}",
+                self.url,
+                resp.status()
+            ));
+        }
+
+        Ok(())
+    }
+}
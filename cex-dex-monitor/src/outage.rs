@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+// OutageTracker turns a stream of request results into one alert when the failures
+// last longer than the window and one when the requests succeed again
+pub struct OutageTracker {
+    window: chrono::Duration,
+    failing_since: Option<chrono::DateTime<chrono::Utc>>,
+    alerted: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outage {
+    Started {
+        since: chrono::DateTime<chrono::Utc>,
+    },
+    Recovered {
+        duration: chrono::Duration,
+    },
+}
+
+impl OutageTracker {
+    pub fn new(window: Duration) -> OutageTracker {
+        OutageTracker {
+            window: chrono::Duration::from_std(window).unwrap_or(chrono::Duration::MAX),
+            failing_since: None,
+            alerted: false,
+        }
+    }
+
+    pub fn record(&mut self, ok: bool, now: chrono::DateTime<chrono::Utc>) -> Option<Outage> {
+        if ok {
+            let since = self.failing_since.take()?;
+            if !std::mem::take(&mut self.alerted) {
+                return None;
+            }
+            return Some(Outage::Recovered {
+                duration: now - since,
+            });
+        }
+
+        let since = *self.failing_since.get_or_insert(now);
+        if self.alerted || now - since < self.window {
+            return None;
+        }
+        self.alerted = true;
+        Some(Outage::Started { since })
+    }
+}
+
+impl Outage {
+    // last_error is the latest failure, shown when the outage starts
+    pub fn message(&self, env: &str, what: &str, last_error: Option<String>) -> String {
+        match self {
+            Outage::Started { since } => {
+                let mut msg = format!(
+                    "> ENV: {}\n{} has been failing since {}",
+                    env,
+                    what,
+                    since.to_rfc3339()
+                );
+                if let Some(e) = last_error {
+                    msg.push_str(&format!("\nlast error: {}", e));
+                }
+                msg
+            }
+            Outage::Recovered { duration } => format!(
+                "> ENV: {}\n{} recovered after {}s",
+                env,
+                what,
+                duration.num_seconds()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Outage, OutageTracker};
+
+    #[test]
+    fn alerts_once_per_outage() {
+        let t0 = chrono::Utc::now();
+        let at = |secs| t0 + chrono::Duration::seconds(secs);
+        let mut t = OutageTracker::new(Duration::from_secs(300));
+
+        assert_eq!(t.record(false, at(0)), None);
+        assert_eq!(t.record(false, at(299)), None);
+        assert_eq!(
+            t.record(false, at(300)),
+            Some(Outage::Started { since: at(0) })
+        );
+        assert_eq!(t.record(false, at(400)), None);
+        assert_eq!(
+            t.record(true, at(500)),
+            Some(Outage::Recovered {
+                duration: chrono::Duration::seconds(500)
+            })
+        );
+        assert_eq!(t.record(true, at(510)), None);
+    }
+
+    #[test]
+    fn started_message_shows_the_last_error() {
+        let since = chrono::DateTime::from_timestamp(0, 0).unwrap();
+        let msg = Outage::Started { since }.message(
+            "prod",
+            "get states",
+            Some("[api_code] api error code 1".to_string()),
+        );
+        assert_eq!(
+            msg,
+            "> ENV: prod\nget states has been failing since 1970-01-01T00:00:00+00:00\n\
+             last error: [api_code] api error code 1"
+        );
+    }
+
+    #[test]
+    fn short_failures_are_silent() {
+        let t0 = chrono::Utc::now();
+        let mut t = OutageTracker::new(Duration::from_secs(300));
+
+        assert_eq!(t.record(false, t0), None);
+        assert_eq!(t.record(true, t0 + chrono::Duration::seconds(60)), None);
+        assert_eq!(t.record(false, t0 + chrono::Duration::seconds(100)), None);
+        assert_eq!(t.record(false, t0 + chrono::Duration::seconds(350)), None);
+    }
+}
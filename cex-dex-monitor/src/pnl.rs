@@ -0,0 +1,502 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, OpenOptions},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use cex_dex_monitor::{AlertKind, PnlConfig};
+use chrono::NaiveDate;
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{
+    cexdexclient::dto::{CexOrderData, StateData},
+    routing::{Alert, Router},
+    scheduler,
+    slackclient::message::Message,
+};
+
+// StatePnl is the realized profit of one finished state, in quote asset
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatePnl {
+    pub date: NaiveDate,
+    pub env: String,
+    pub state_id: String,
+    pub token: String,
+    pub side: String,
+    pub quote: String,
+    // asset changes before fees, the token leg valued at the p1 price
+    pub gross: Decimal,
+    pub cex_fee: Decimal,
+    pub gas: Decimal,
+    pub net: Decimal,
+    // fee assets that could not be valued, ';' separated
+    pub unpriced_fees: String,
+}
+
+pub fn compute(state: &StateData, env: &str, cfg: &PnlConfig) -> StatePnl {
+    let quote = cfg.quote_asset.as_str();
+    let ref_price = match state.cex_price(&state.p1_cex_orders) {
+        p if p > Decimal::ZERO => p,
+        _ => state.cex_price(&state.p2_cex_orders),
+    };
+
+    let (quote_change, token_change) = match &state.asset_change {
+        None => (Decimal::ZERO, Decimal::ZERO),
+        Some(m) => (
+            m.get(quote).copied().unwrap_or_default(),
+            m.get(&state.token).copied().unwrap_or_default(),
+        ),
+    };
+    let gross = quote_change + token_change * ref_price;
+
+    let mut unpriced = BTreeSet::<String>::new();
+    let mut cex_fee = Decimal::ZERO;
+    for o in cex_orders(&state.p1_cex_orders).chain(cex_orders(&state.p2_cex_orders)) {
+        if o.fee_amount.is_zero() {
+            continue;
+        }
+        if o.fee_asset.eq(quote) {
+            cex_fee += o.fee_amount;
+        } else if o.fee_asset.eq(&o.base_symbol) {
+            cex_fee += o.fee_amount * to_decimal(o.actual_price);
+        } else {
+            unpriced.insert(o.fee_asset.clone());
+        }
+    }
+
+    let gas_unit = gas_unit(cfg.gas_price_decimals);
+    let gas = match &state.p2_dex_txs {
+        None => Decimal::ZERO,
+        Some(txs) => txs
+            .iter()
+            .map(|tx| {
+                Decimal::from(tx.gas_used)
+                    * to_decimal(tx.gas_price)
+                    * gas_unit
+                    * to_decimal(tx.native_token_price_in_quote)
+            })
+            .sum(),
+    };
+
+    StatePnl {
+        date: state_date(state),
+        env: env.to_string(),
+        state_id: state.state_id.clone(),
+        token: state.token.clone(),
+        side: state.side.clone(),
+        quote: quote.to_string(),
+        gross: gross.normalize(),
+        cex_fee: cex_fee.normalize(),
+        gas: gas.normalize(),
+        net: (gross - cex_fee - gas).normalize(),
+        unpriced_fees: unpriced.into_iter().collect::<Vec<String>>().join(";"),
+    }
+}
+
+// the prices of the api are f64
+fn to_decimal(f: f64) -> Decimal {
+    Decimal::from_f64(f).unwrap_or_default()
+}
+
+// 10^-decimals
+fn gas_unit(decimals: i32) -> Decimal {
+    match u32::try_from(decimals) {
+        Ok(d) => Decimal::new(1, d.min(Decimal::MAX_SCALE)),
+        Err(_) => Decimal::from(10u64.saturating_pow(decimals.unsigned_abs())),
+    }
+}
+
+fn cex_orders(o: &Option<Vec<CexOrderData>>) -> impl Iterator<Item = &CexOrderData> {
+    o.iter().flat_map(|v| v.iter())
+}
+
+// falls back to today when created_time is not parsable
+fn state_date(state: &StateData) -> NaiveDate {
+    match state.created_at() {
+        Some(t) => t.date_naive(),
+        None => scheduler::now().date_naive(),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DailyPnl {
+    pub date: NaiveDate,
+    pub env: String,
+    pub token: String,
+    pub side: String,
+    pub quote: String,
+    pub states: usize,
+    pub gross: Decimal,
+    pub cex_fee: Decimal,
+    pub gas: Decimal,
+    pub net: Decimal,
+}
+
+// sum the states per day, env, token and side
+pub fn aggregate(rows: &[StatePnl]) -> Vec<DailyPnl> {
+    let mut days = BTreeMap::<(NaiveDate, String, String, String), DailyPnl>::new();
+    for r in rows {
+        let key = (r.date, r.env.clone(), r.token.clone(), r.side.clone());
+        let d = days.entry(key).or_insert_with(|| DailyPnl {
+            date: r.date,
+            env: r.env.clone(),
+            token: r.token.clone(),
+            side: r.side.clone(),
+            quote: r.quote.clone(),
+            states: 0,
+            gross: Decimal::ZERO,
+            cex_fee: Decimal::ZERO,
+            gas: Decimal::ZERO,
+            net: Decimal::ZERO,
+        });
+        d.states += 1;
+        d.gross += r.gross;
+        d.cex_fee += r.cex_fee;
+        d.gas += r.gas;
+        d.net += r.net;
+    }
+
+    days.into_values().collect()
+}
+
+// Ledger appends the pnl rows to csv files, the state file is read back for the daily summary
+pub struct Ledger {
+    states_path: PathBuf,
+    daily_path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl Ledger {
+    pub fn new(cfg: &PnlConfig) -> Ledger {
+        Ledger {
+            states_path: PathBuf::from(&cfg.csv_path),
+            daily_path: PathBuf::from(&cfg.daily_csv_path),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn append(&self, row: &StatePnl) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        append_rows(&self.states_path, std::slice::from_ref(row))
+    }
+
+    pub fn append_daily(&self, rows: &[DailyPnl]) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        append_rows(&self.daily_path, rows)
+    }
+
+    pub fn states(&self) -> anyhow::Result<Vec<StatePnl>> {
+        let _guard = self.lock.lock().unwrap();
+        read_rows(&self.states_path)
+    }
+
+    // summarized states per day and env
+    pub fn summarized_states(&self) -> anyhow::Result<BTreeMap<(NaiveDate, String), usize>> {
+        let _guard = self.lock.lock().unwrap();
+        let mut summarized = BTreeMap::new();
+        for d in read_rows::<DailyPnl>(&self.daily_path)? {
+            *summarized.entry((d.date, d.env)).or_default() += d.states;
+        }
+        Ok(summarized)
+    }
+}
+
+fn append_rows<T: Serialize>(path: &Path, rows: &[T]) -> anyhow::Result<()> {
+    // an empty path keeps nothing, used by replays
+    if path.as_os_str().is_empty() {
+        return Ok(());
+    }
+    let is_new = fs::metadata(path).map(|m| m.len() == 0).unwrap_or(true);
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut w = csv::WriterBuilder::new()
+        .has_headers(is_new)
+        .from_writer(file);
+    for r in rows {
+        w.serialize(r)?;
+    }
+    w.flush()?;
+
+    Ok(())
+}
+
+fn read_rows<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<Vec<T>> {
+    let mut r = match csv::Reader::from_path(path) {
+        Ok(r) => r,
+        Err(e) => match e.kind() {
+            csv::ErrorKind::Io(io) if io.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Vec::new())
+            }
+            _ => return Err(e.into()),
+        },
+    };
+
+    let mut rows = Vec::new();
+    for row in r.deserialize() {
+        rows.push(row?);
+    }
+    Ok(rows)
+}
+
+// wait before summarizing again after a failure
+const RETRY_DELAY: Duration = Duration::from_secs(300);
+
+// posts the summary of every finished day not summarized yet, shortly after each utc midnight
+pub async fn run_daily_summary(ledger: Arc<Ledger>, router: Router, shutdown: CancellationToken) {
+    info!("pnl daily summary started");
+    loop {
+        let today = scheduler::now().date_naive();
+        let wait = match summarize_before(&ledger, &router, today).await {
+            Ok(()) => {
+                let next = (today + chrono::Duration::days(1))
+                    .and_hms_opt(0, 5, 0)
+                    .unwrap()
+                    .and_utc();
+                (next - scheduler::now())
+                    .to_std()
+                    .unwrap_or(Duration::from_secs(60))
+            }
+            Err(e) => {
+                warn!(error = %e, retry_in = ?RETRY_DELAY, "pnl daily summary failed");
+                RETRY_DELAY
+            }
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = shutdown.cancelled() => {
+                info!("pnl daily summary stopped");
+                return;
+            }
+        }
+    }
+}
+
+// the states of a day are appended in order, the ones after the summarized count
+// came late and get a summary of their own
+async fn summarize_before(
+    ledger: &Ledger,
+    router: &Router,
+    today: NaiveDate,
+) -> anyhow::Result<()> {
+    let summarized = ledger.summarized_states()?;
+    let mut seen = BTreeMap::<(NaiveDate, String), usize>::new();
+    let rows = ledger
+        .states()?
+        .into_iter()
+        .filter(|r| r.date < today)
+        .filter(|r| {
+            let key = (r.date, r.env.clone());
+            let n = seen.entry(key.clone()).or_default();
+            *n += 1;
+            *n > summarized.get(&key).copied().unwrap_or_default()
+        })
+        .collect::<Vec<StatePnl>>();
+
+    let mut per_day_env = BTreeMap::<(NaiveDate, String), Vec<DailyPnl>>::new();
+    for d in aggregate(&rows) {
+        per_day_env
+            .entry((d.date, d.env.clone()))
+            .or_default()
+            .push(d);
+    }
+
+    for ((date, env), days) in per_day_env {
+        let late = summarized.contains_key(&(date, env.clone()));
+        let alert = Alert::new(AlertKind::PnlDaily, &env)
+            .with_assets(days.iter().map(|d| (d.token.clone(), d.net)).collect());
+        router
+            .send(&alert, daily_message(date, &env, &days, late))
+            .await?;
+        ledger.append_daily(&days)?;
+        info!(%date, %env, late, "sent pnl summary");
+    }
+
+    Ok(())
+}
+
+// late: the states of a day summarized before, arrived after its summary
+fn daily_message(date: NaiveDate, env: &str, days: &[DailyPnl], late: bool) -> Message {
+    let total: Decimal = days.iter().map(|d| d.net).sum();
+    let quote = days.first().map(|d| d.quote.as_str()).unwrap_or_default();
+    let title = if late {
+        "DAILY PNL, LATE STATES"
+    } else {
+        "DAILY PNL"
+    };
+
+    let mut fallback = format!(
+        "*****\n*{}*\n> ENV: {}\n> DATE: {}\nTOTAL NET: {} {}\n",
+        title, env, date, total, quote
+    );
+    let mut lines = Vec::<String>::new();
+    for d in days {
+        let line = format!(
+            "{} {}: {} states, gross {}, cex fee {}, gas {}, net {}",
+            d.token, d.side, d.states, d.gross, d.cex_fee, d.gas, d.net
+        );
+        fallback.push_str(&line);
+        fallback.push('\n');
+        lines.push(format!("`{}`", line));
+    }
+    fallback.push_str("*****");
+
+    Message::builder(fallback)
+        .header(title)
+        .fields(vec![
+            ("ENV", env.to_string()),
+            ("DATE", date.to_string()),
+            ("TOTAL NET", format!("{} {}", total, quote)),
+        ])
+        .markdown(&lines.join("\n"))
+        .diff_rows(
+            "NET PER TOKEN/SIDE",
+            &days
+                .iter()
+                .map(|d| (format!("{} {}", d.token, d.side), d.net))
+                .collect::<Vec<(String, Decimal)>>(),
+        )
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use async_trait::async_trait;
+    use cex_dex_monitor::{AlertKind, AlertRoutingConfig, PnlConfig};
+    use chrono::NaiveDate;
+    use rust_decimal_macros::dec;
+
+    use super::{aggregate, compute, summarize_before, Ledger};
+    use crate::{
+        notifier::Notifier, routing::Router, slackclient::message::Message, testutil::state,
+    };
+
+    fn ledger(name: &str) -> (Ledger, std::path::PathBuf) {
+        let dir = env::temp_dir().join(format!(
+            "cex-dex-monitor-pnl-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let cfg = PnlConfig {
+            csv_path: dir.join("states.csv").to_string_lossy().to_string(),
+            daily_csv_path: dir.join("daily.csv").to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        (Ledger::new(&cfg), dir)
+    }
+
+    // records the sent texts, fails while down
+    #[derive(Default)]
+    struct Sent {
+        texts: Mutex<Vec<String>>,
+        down: AtomicBool,
+    }
+
+    #[async_trait]
+    impl Notifier for Sent {
+        fn has_channel(&self, _: &str) -> bool {
+            true
+        }
+
+        async fn send(&self, _: &str, _: AlertKind, msg: &Message) -> anyhow::Result<()> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(anyhow::format_err!("down"));
+            }
+            self.texts.lock().unwrap().push(msg.text.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn state_pnl_includes_fees_and_gas() {
+        let p = compute(&state(), "prod", &PnlConfig::default());
+
+        assert_eq!(p.date, NaiveDate::from_ymd_opt(2024, 1, 18).unwrap());
+        assert_eq!(p.gross, dec!(1.2));
+        assert_eq!(p.cex_fee, dec!(0.04));
+        assert_eq!(p.gas, dec!(0.005));
+        assert_eq!(p.net, dec!(1.155));
+        assert_eq!(p.unpriced_fees, "BNB");
+    }
+
+    #[test]
+    fn ledger_round_trip_and_aggregate() {
+        let (ledger, dir) = ledger("round-trip");
+
+        let a = compute(&state(), "prod", &PnlConfig::default());
+        let mut b = a.clone();
+        b.state_id = "s2".to_string();
+        b.net = dec!(2);
+        let mut c = a.clone();
+        c.side = "SELL".to_string();
+        ledger.append(&a).unwrap();
+        ledger.append(&b).unwrap();
+        ledger.append(&c).unwrap();
+
+        let rows = ledger.states().unwrap();
+        assert_eq!(rows, vec![a.clone(), b, c]);
+
+        let days = aggregate(&rows);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].side, "BUY");
+        assert_eq!(days[0].states, 2);
+        assert_eq!(days[0].net, a.net + dec!(2));
+
+        ledger.append_daily(&days).unwrap();
+        assert_eq!(
+            ledger.summarized_states().unwrap()[&(a.date, "prod".to_string())],
+            3
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn late_states_get_their_own_summary() {
+        let (ledger, dir) = ledger("late");
+        let sent = Arc::new(Sent::default());
+        let router = Router::new(&AlertRoutingConfig::default(), sent.clone());
+        let a = compute(&state(), "prod", &PnlConfig::default());
+        let today = a.date.succ_opt().unwrap();
+        ledger.append(&a).unwrap();
+
+        // a failed send is not recorded as summarized
+        sent.down.store(true, Ordering::SeqCst);
+        assert!(summarize_before(&ledger, &router, today).await.is_err());
+        assert!(ledger.summarized_states().unwrap().is_empty());
+        sent.down.store(false, Ordering::SeqCst);
+        summarize_before(&ledger, &router, today).await.unwrap();
+        assert_eq!(sent.texts.lock().unwrap().len(), 1);
+
+        let mut late = a.clone();
+        late.state_id = "s2".to_string();
+        late.net = dec!(2);
+        ledger.append(&late).unwrap();
+        summarize_before(&ledger, &router, today).await.unwrap();
+        summarize_before(&ledger, &router, today).await.unwrap();
+
+        let texts = sent.texts.lock().unwrap().clone();
+        assert_eq!(texts.len(), 2);
+        assert!(texts[1].contains("DAILY PNL, LATE STATES"), "{}", texts[1]);
+        assert!(texts[1].contains("1 states"), "{}", texts[1]);
+        assert!(texts[1].contains("TOTAL NET: 2 USDT"), "{}", texts[1]);
+        assert_eq!(
+            ledger.summarized_states().unwrap()[&(a.date, "prod".to_string())],
+            2
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,56 @@
+use std::{collections::HashMap, time::Duration};
+
+use async_trait::async_trait;
+use cex_dex_monitor::PricingConfig;
+use rust_decimal::Decimal;
+
+use super::{fixed::FixedPriceProvider, PriceProvider};
+
+// HttpPriceProvider asks GET <url>?symbols=A,B for {"A": price, "B": price},
+// the fixed prices of the config fill the assets it does not return
+pub struct HttpPriceProvider {
+    client: reqwest::Client,
+    url: String,
+    timeout: Duration,
+    fallback: FixedPriceProvider,
+}
+
+impl HttpPriceProvider {
+    pub fn new(cfg: &PricingConfig) -> HttpPriceProvider {
+        HttpPriceProvider {
+            client: reqwest::Client::new(),
+            url: cfg.url.clone(),
+            timeout: Duration::from_millis(cfg.timeout_ms),
+            fallback: FixedPriceProvider::new(cfg.prices.clone()),
+        }
+    }
+}
+
+#[async_trait]
+impl PriceProvider for HttpPriceProvider {
+    async fn usd_prices(&self, assets: &[String]) -> anyhow::Result<HashMap<String, Decimal>> {
+        let resp = self
+            .client
+            .get(&self.url)
+            .query(&[("symbols", assets.join(","))])
+            .timeout(self.timeout)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(anyhow::format_err!(
+                "price url [{}] status {}",
+                self.url,
+                resp.status()
+            ));
+        }
+        let fetched: HashMap<String, Decimal> = resp.json().await?;
+
+        let mut prices = self.fallback.usd_prices(assets).await?;
+        for (asset, price) in fetched {
+            if assets.contains(&asset) {
+                prices.insert(asset, price);
+            }
+        }
+        Ok(prices)
+    }
+}
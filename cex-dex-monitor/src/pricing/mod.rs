@@ -0,0 +1,63 @@
+pub mod fixed;
+pub mod http;
+mod test;
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use cex_dex_monitor::{PriceProviderKind, PricingConfig};
+use rust_decimal::Decimal;
+
+// PriceProvider returns the usd price of the assets it knows, the others are left out
+#[async_trait]
+pub trait PriceProvider: Send + Sync {
+    async fn usd_prices(&self, assets: &[String]) -> anyhow::Result<HashMap<String, Decimal>>;
+}
+
+pub fn from_config(cfg: &PricingConfig) -> Arc<dyn PriceProvider> {
+    match cfg.kind {
+        PriceProviderKind::Fixed => Arc::new(fixed::FixedPriceProvider::new(cfg.prices.clone())),
+        PriceProviderKind::Http => Arc::new(http::HttpPriceProvider::new(cfg)),
+    }
+}
+
+// Valuation values balances at the prices fetched once per check
+#[derive(Debug, Clone, Default)]
+pub struct Valuation {
+    prices: HashMap<String, Decimal>,
+}
+
+impl Valuation {
+    pub fn new(prices: HashMap<String, Decimal>) -> Valuation {
+        Valuation { prices }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prices.is_empty()
+    }
+
+    pub fn usd(&self, asset: &str, amount: Decimal) -> Option<Decimal> {
+        self.prices.get(asset).map(|p| (amount * p).round_dp(2))
+    }
+
+    // value of the priced assets, None when no asset is priced
+    pub fn total(&self, balances: &HashMap<String, Decimal>) -> Option<Decimal> {
+        let mut priced = false;
+        let mut total = Decimal::ZERO;
+        for (asset, amount) in balances.iter() {
+            if let Some(v) = self.usd(asset, *amount) {
+                priced = true;
+                total += v;
+            }
+        }
+        priced.then_some(total)
+    }
+
+    // "<amount> ($<usd>)", the amount alone when the asset is not priced
+    pub fn format(&self, asset: &str, amount: Decimal) -> String {
+        match self.usd(asset, amount) {
+            None => amount.to_string(),
+            Some(v) => format!("{} (${})", amount, v),
+        }
+    }
+}
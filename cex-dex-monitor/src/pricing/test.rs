@@ -0,0 +1,74 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use cex_dex_monitor::{PriceProviderKind, PricingConfig};
+    use rust_decimal_macros::dec;
+
+    use crate::{
+        pricing::{from_config, Valuation},
+        testutil::{ok, status, MockServer},
+    };
+
+    fn assets(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn http_prices_are_completed_by_fixed_prices() {
+        let server = MockServer::start().await;
+        server.respond("/prices", vec![ok(r#"{"KNC": 0.55, "ETH": 3000}"#)]);
+        let cfg = PricingConfig {
+            kind: PriceProviderKind::Http,
+            url: format!("{}/prices", server.url),
+            ..Default::default()
+        };
+
+        let prices = from_config(&cfg)
+            .usd_prices(&assets(&["KNC", "USDT", "XYZ"]))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            prices,
+            HashMap::from([
+                ("KNC".to_string(), dec!(0.55)),
+                ("USDT".to_string(), dec!(1)),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn http_errors_are_returned() {
+        let server = MockServer::start().await;
+        server.respond("/prices", vec![status(503)]);
+        let cfg = PricingConfig {
+            kind: PriceProviderKind::Http,
+            url: format!("{}/prices", server.url),
+            ..Default::default()
+        };
+
+        assert!(from_config(&cfg)
+            .usd_prices(&assets(&["KNC"]))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn valuation_skips_unpriced_assets() {
+        let v = Valuation::new(HashMap::from([
+            ("KNC".to_string(), dec!(0.5)),
+            ("USDT".to_string(), dec!(1)),
+        ]));
+        let balances = HashMap::from([
+            ("KNC".to_string(), dec!(10.001)),
+            ("USDT".to_string(), dec!(100)),
+            ("XYZ".to_string(), dec!(7)),
+        ]);
+
+        assert_eq!(v.total(&balances), Some(dec!(105)));
+        assert_eq!(v.format("KNC", dec!(10.001)), "10.001 ($5.00)");
+        assert_eq!(v.format("XYZ", dec!(7)), "7");
+        assert_eq!(Valuation::default().total(&balances), None);
+    }
+}
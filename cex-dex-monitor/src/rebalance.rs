@@ -0,0 +1,152 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+
+// RebalanceTracker follows the venues moving funds between cex and dex: when each
+// rebalancing started, and one alert when it lasts longer than the window
+pub struct RebalanceTracker {
+    window: chrono::Duration,
+    // venue -> rebalancing
+    venues: BTreeMap<String, Rebalancing>,
+}
+
+struct Rebalancing {
+    since: DateTime<Utc>,
+    alerted: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RebalanceEvent {
+    Started {
+        venue: String,
+    },
+    TooLong {
+        venue: String,
+        since: DateTime<Utc>,
+    },
+    Finished {
+        venue: String,
+        duration: chrono::Duration,
+        // a TooLong was sent for it
+        alerted: bool,
+    },
+}
+
+impl RebalanceTracker {
+    pub fn new(window: Duration) -> RebalanceTracker {
+        RebalanceTracker {
+            window: chrono::Duration::from_std(window).unwrap_or(chrono::Duration::MAX),
+            venues: BTreeMap::new(),
+        }
+    }
+
+    // rebalancing: the venues rebalancing in the last fetch
+    pub fn record(
+        &mut self,
+        rebalancing: &BTreeSet<String>,
+        now: DateTime<Utc>,
+    ) -> Vec<RebalanceEvent> {
+        let mut events = Vec::new();
+
+        let finished = self
+            .venues
+            .keys()
+            .filter(|v| !rebalancing.contains(*v))
+            .cloned()
+            .collect::<Vec<String>>();
+        for venue in finished {
+            let r = self.venues.remove(&venue).unwrap();
+            events.push(RebalanceEvent::Finished {
+                venue,
+                duration: now - r.since,
+                alerted: r.alerted,
+            });
+        }
+
+        for venue in rebalancing {
+            let r = self.venues.entry(venue.clone()).or_insert_with(|| {
+                events.push(RebalanceEvent::Started {
+                    venue: venue.clone(),
+                });
+                Rebalancing {
+                    since: now,
+                    alerted: false,
+                }
+            });
+            if !r.alerted && now - r.since >= self.window {
+                r.alerted = true;
+                events.push(RebalanceEvent::TooLong {
+                    venue: venue.clone(),
+                    since: r.since,
+                });
+            }
+        }
+
+        events
+    }
+
+    pub fn is_rebalancing(&self) -> bool {
+        !self.venues.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, time::Duration};
+
+    use super::{RebalanceEvent, RebalanceTracker};
+
+    fn venues(v: &[&str]) -> BTreeSet<String> {
+        v.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn tracks_each_venue() {
+        let t0 = chrono::Utc::now();
+        let at = |secs| t0 + chrono::Duration::seconds(secs);
+        let mut t = RebalanceTracker::new(Duration::from_secs(600));
+
+        assert_eq!(
+            t.record(&venues(&["cex/binance"]), at(0)),
+            vec![RebalanceEvent::Started {
+                venue: "cex/binance".to_string()
+            }]
+        );
+        assert_eq!(
+            t.record(&venues(&["cex/binance", "dex/polygon"]), at(300)),
+            vec![RebalanceEvent::Started {
+                venue: "dex/polygon".to_string()
+            }]
+        );
+        assert_eq!(
+            t.record(&venues(&["cex/binance", "dex/polygon"]), at(600)),
+            vec![RebalanceEvent::TooLong {
+                venue: "cex/binance".to_string(),
+                since: at(0)
+            }]
+        );
+        // alerted once per rebalancing
+        assert!(t
+            .record(&venues(&["cex/binance", "dex/polygon"]), at(700))
+            .is_empty());
+        assert_eq!(
+            t.record(&venues(&[]), at(800)),
+            vec![
+                RebalanceEvent::Finished {
+                    venue: "cex/binance".to_string(),
+                    duration: chrono::Duration::seconds(800),
+                    alerted: true
+                },
+                RebalanceEvent::Finished {
+                    venue: "dex/polygon".to_string(),
+                    duration: chrono::Duration::seconds(500),
+                    alerted: false
+                }
+            ]
+        );
+        assert!(!t.is_rebalancing());
+    }
+}
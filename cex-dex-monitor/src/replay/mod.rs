@@ -0,0 +1,134 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use cex_dex_monitor::{AlertKind, Config, PriceProviderKind};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    cexdexclient::recorder::{self, Replay},
+    notifier::Notifier,
+    pnl, pricing,
+    routing::Router,
+    scheduler,
+    slackclient::message::Message,
+    store::{self, memory::MemoryStore},
+    supervisor::Supervisor,
+};
+
+mod test;
+
+// replay runs the monitors of cfg against a recording, on a paused clock starting at
+// the first record, and writes the alerts to out instead of slack
+#[cfg(feature = "replay")]
+pub fn run(recording: &str, cfg: Config, out: Box<dyn Write + Send>) -> anyhow::Result<()> {
+    let replay = Replay::load(recording)?;
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .start_paused(true)
+        .build()?
+        .block_on(play(replay, cfg, out))
+}
+
+#[cfg(not(feature = "replay"))]
+pub fn run(_: &str, _: Config, _: Box<dyn Write + Send>) -> anyhow::Result<()> {
+    Err(anyhow::format_err!(
+        "built without replay, build with --features replay"
+    ))
+}
+
+pub async fn play(
+    replay: Replay,
+    mut cfg: Config,
+    out: Box<dyn Write + Send>,
+) -> anyhow::Result<()> {
+    let (start, end) = replay
+        .span()
+        .ok_or_else(|| anyhow::format_err!("empty recording"))?;
+    prepare(&mut cfg);
+    recorder::set_replay(Some(Arc::new(replay)));
+    scheduler::start_at(start);
+
+    let router = Router::new(&cfg.alert_routing, Arc::new(TextSink::new(out)));
+    let store: Arc<dyn store::Store> = Arc::new(MemoryStore::new());
+    let mut supervisor = Supervisor::new(
+        router,
+        store,
+        Arc::new(pnl::Ledger::new(&cfg.pnl)),
+        pricing::from_config(&cfg.pricing),
+        // the replayed balances are not kept
+        None,
+        CancellationToken::new(),
+    );
+    let cfg = Arc::new(cfg);
+    supervisor.apply(cfg.clone()).await;
+
+    // one more state poll after the last record
+    let tail = Duration::from_secs(cfg.scheduler.state_poll.period_secs);
+    tokio::select! {
+        _ = tokio::time::sleep((end - start).to_std().unwrap_or_default() + tail) => {}
+        _ = supervisor.supervise() => {}
+    }
+
+    let stopped = supervisor
+        .stop(Duration::from_secs(cfg.scheduler.shutdown_grace_secs))
+        .await;
+    recorder::set_replay(None);
+    if !stopped {
+        return Err(anyhow::format_err!("monitors did not stop"));
+    }
+    Ok(())
+}
+
+// same timings on every run and nothing written but the alerts
+fn prepare(cfg: &mut Config) {
+    for i in [
+        &mut cfg.scheduler.state_poll,
+        &mut cfg.scheduler.running_poll,
+        &mut cfg.scheduler.balance_fetch,
+        &mut cfg.scheduler.balance_check,
+    ] {
+        i.jitter_secs = 0;
+    }
+    cfg.pnl.csv_path.clear();
+    cfg.pnl.daily_csv_path.clear();
+    cfg.pricing.kind = PriceProviderKind::Fixed;
+    cfg.cex_dex_client.record_path.clear();
+}
+
+// TextSink writes every alert with its time, channel and kind
+pub struct TextSink {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl TextSink {
+    pub fn new(out: Box<dyn Write + Send>) -> TextSink {
+        TextSink {
+            out: Mutex::new(out),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for TextSink {
+    fn has_channel(&self, _: &str) -> bool {
+        true
+    }
+
+    async fn send(&self, channel: &str, kind: AlertKind, msg: &Message) -> anyhow::Result<()> {
+        let mut out = self.out.lock().unwrap();
+        writeln!(
+            out,
+            "--- {} #{} {:?}\n{}\n",
+            scheduler::now().to_rfc3339(),
+            channel,
+            kind,
+            msg.text
+        )?;
+        out.flush()?;
+        Ok(())
+    }
+}
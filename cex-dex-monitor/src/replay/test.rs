@@ -0,0 +1,168 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use cex_dex_monitor::Config;
+    use rust_decimal_macros::dec;
+    use serde::Serialize;
+
+    use crate::{
+        cexdexclient::{
+            dto::{
+                CEXBalance, CEXBalanceData, DEXBalanceData, GetCEXBalanceResponse,
+                GetDEXBalanceResponse, Response, ResponseResult, StateData,
+            },
+            recorder::{Record, Replay},
+        },
+        replay::play,
+        testutil,
+    };
+
+    const BASE_URL: &str = "http://replay-golden";
+    const DONE_QUERY: &str = "is_done=true&fill_state=true&size=20";
+    const RUNNING_QUERY: &str = "is_done=false&fill_state=true&size=100";
+    const GOLDEN: &str = "src/replay/testdata/alerts.golden";
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn record(secs: i64, path: &str, query: &str, body: &impl Serialize) -> Record {
+        Record {
+            at: "2024-01-18T10:00:00Z"
+                .parse::<chrono::DateTime<chrono::Utc>>()
+                .unwrap()
+                + chrono::Duration::seconds(secs),
+            base_url: BASE_URL.to_string(),
+            path: path.to_string(),
+            query: query.to_string(),
+            status: 200,
+            body: serde_json::to_string(body).unwrap(),
+        }
+    }
+
+    fn ok() -> ResponseResult {
+        ResponseResult {
+            code: 0,
+            message: String::new(),
+        }
+    }
+
+    fn states(data: Vec<StateData>) -> Response {
+        Response { result: ok(), data }
+    }
+
+    fn state(id: &str) -> StateData {
+        let mut s = testutil::state();
+        s.state_id = id.to_string();
+        s
+    }
+
+    fn balances(usdt: rust_decimal::Decimal, knc: rust_decimal::Decimal) -> [Record; 2] {
+        let cex = GetCEXBalanceResponse {
+            result: ok(),
+            data: CEXBalanceData {
+                id: "binance".to_string(),
+                is_rebalancing: false,
+                balances: [(
+                    "USDT".to_string(),
+                    CEXBalance {
+                        free: usdt,
+                        locked: dec!(0),
+                    },
+                )]
+                .into(),
+            },
+        };
+        let dex = GetDEXBalanceResponse {
+            result: ok(),
+            data: DEXBalanceData {
+                id: "polygon".to_string(),
+                is_rebalancing: false,
+                balances: [("KNC".to_string(), knc)].into(),
+                contract_balances: Default::default(),
+            },
+        };
+        [
+            record(0, "/cex/binance/balances", "", &cex),
+            record(0, "/dex/polygon/balances", "", &dex),
+        ]
+    }
+
+    fn at(secs: i64, records: [Record; 2]) -> Vec<Record> {
+        records
+            .into_iter()
+            .map(|mut r| {
+                r.at += chrono::Duration::seconds(secs);
+                r
+            })
+            .collect()
+    }
+
+    fn recording() -> Vec<Record> {
+        // running for 20 minutes, the watchdog reports it as stuck
+        let mut running = state("r1");
+        running.is_done = false;
+        running.created_time = "2024-01-18T09:40:00Z".to_string();
+        running.p2_dex_txs = None;
+
+        let mut failed = state("s2");
+        failed.p2_dex_txs.as_mut().unwrap()[0].status = "FAILED".to_string();
+
+        let mut records = vec![
+            record(0, "/state", DONE_QUERY, &states(vec![state("s0")])),
+            record(
+                60,
+                "/state",
+                DONE_QUERY,
+                &states(vec![state("s0"), state("s1"), failed]),
+            ),
+            record(0, "/state", RUNNING_QUERY, &states(vec![running])),
+            record(120, "/state", RUNNING_QUERY, &states(Vec::new())),
+        ];
+        records.extend(at(0, balances(dec!(1000), dec!(100))));
+        records.extend(at(1800, balances(dec!(1020), dec!(90))));
+        records.extend(at(3800, balances(dec!(1020), dec!(90))));
+        records
+    }
+
+    // UPDATE_GOLDEN=1 cargo test replay rewrites the golden file
+    #[tokio::test(start_paused = true)]
+    async fn replay_matches_golden_alerts() {
+        let cfg: Config = serde_yaml::from_str(&format!(
+            "
+cex_dex_config:
+  - env: prod
+    urls:
+      - {{base_url: \"{}\", user: u, pass: p}}
+slack_client_config: {{}}
+",
+            BASE_URL
+        ))
+        .unwrap();
+        let out = Buffer::default();
+
+        play(Replay::new(recording()), cfg, Box::new(out.clone()))
+            .await
+            .unwrap();
+
+        let got = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        if std::env::var("UPDATE_GOLDEN").is_ok() {
+            fs::write(GOLDEN, &got).unwrap();
+        }
+        assert_eq!(got, fs::read_to_string(GOLDEN).unwrap());
+    }
+}
@@ -0,0 +1,334 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use cex_dex_monitor::{AlertKind, AlertRouteConfig, AlertRoutingConfig};
+use rust_decimal::Decimal;
+use tracing::info;
+
+use crate::{notifier::Notifier, scheduler, slackclient::message::Message};
+
+// Alert describes what is about to be sent, routes are matched against it
+pub struct Alert<'a> {
+    pub kind: AlertKind,
+    pub env: &'a str,
+    // asset -> change (or amount for snapshots), used by the route filters
+    pub assets: Vec<(String, Decimal)>,
+}
+
+impl<'a> Alert<'a> {
+    pub fn new(kind: AlertKind, env: &'a str) -> Alert<'a> {
+        Alert {
+            kind,
+            env,
+            assets: Vec::new(),
+        }
+    }
+
+    pub fn with_assets(mut self, assets: Vec<(String, Decimal)>) -> Alert<'a> {
+        self.assets = assets;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(tag = "kind", content = "name", rename_all = "lowercase")]
+pub enum SilenceTarget {
+    Env(String),
+    Channel(String),
+}
+
+// Silences mutes the alerts of an env or a channel until a deadline
+#[derive(Default)]
+pub struct Silences {
+    until: Mutex<HashMap<SilenceTarget, chrono::DateTime<chrono::Utc>>>,
+}
+
+impl Silences {
+    pub fn silence(&self, target: SilenceTarget, until: chrono::DateTime<chrono::Utc>) {
+        self.until.lock().unwrap().insert(target, until);
+    }
+
+    // false when the target was not silenced
+    pub fn lift(&self, target: &SilenceTarget) -> bool {
+        self.until.lock().unwrap().remove(target).is_some()
+    }
+
+    // the silences still running, the expired ones are dropped
+    pub fn active(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<(SilenceTarget, chrono::DateTime<chrono::Utc>)> {
+        let mut until = self.until.lock().unwrap();
+        until.retain(|_, t| *t > now);
+        until.iter().map(|(k, t)| (k.clone(), *t)).collect()
+    }
+
+    fn is_silenced(&self, env: &str, channel: &str, now: chrono::DateTime<chrono::Utc>) -> bool {
+        let until = self.until.lock().unwrap();
+        [
+            SilenceTarget::Env(env.to_string()),
+            SilenceTarget::Channel(channel.to_string()),
+        ]
+        .iter()
+        .any(|t| until.get(t).is_some_and(|u| *u > now))
+    }
+}
+
+// the routes and notifier are shared by the clones, so a reload reaches every monitor
+#[derive(Clone)]
+pub struct Router {
+    inner: Arc<RwLock<Routes>>,
+    silences: Arc<Silences>,
+}
+
+struct Routes {
+    routes: Vec<AlertRouteConfig>,
+    notifier: Arc<dyn Notifier>,
+}
+
+impl Router {
+    pub fn new(cfg: &AlertRoutingConfig, notifier: Arc<dyn Notifier>) -> Router {
+        if let Err(e) = validate(cfg, notifier.as_ref()) {
+            panic!("{}", e);
+        }
+
+        Router {
+            inner: Arc::new(RwLock::new(Routes {
+                routes: cfg.routes.clone(),
+                notifier,
+            })),
+            silences: Arc::new(Silences::default()),
+        }
+    }
+
+    // swap the routes and notifier, the current ones are kept when cfg is invalid
+    pub fn reload(
+        &self,
+        cfg: &AlertRoutingConfig,
+        notifier: Arc<dyn Notifier>,
+    ) -> anyhow::Result<()> {
+        validate(cfg, notifier.as_ref())?;
+        *self.inner.write().unwrap() = Routes {
+            routes: cfg.routes.clone(),
+            notifier,
+        };
+        Ok(())
+    }
+
+    pub fn silences(&self) -> Arc<Silences> {
+        self.silences.clone()
+    }
+
+    // channels of every matched route, without duplicates
+    pub fn channels(&self, alert: &Alert) -> Vec<String> {
+        let mut channels = Vec::<String>::new();
+        let inner = self.inner.read().unwrap();
+        for r in inner.routes.iter().filter(|r| route_matches(r, alert)) {
+            for c in r.channels.iter() {
+                if !channels.contains(c) {
+                    channels.push(c.clone());
+                }
+            }
+        }
+
+        channels
+    }
+
+    // send msg to every matched channel, an error on one channel does not stop the others
+    pub async fn send(&self, alert: &Alert<'_>, msg: impl Into<Message>) -> anyhow::Result<()> {
+        let msg = msg.into();
+        let notifier = self.inner.read().unwrap().notifier.clone();
+        let mut errors = Vec::<String>::new();
+        for c in self.channels(alert) {
+            if self.silences.is_silenced(alert.env, &c, scheduler::now()) {
+                info!(kind = ?alert.kind, env = alert.env, channel = %c, "alert silenced");
+                continue;
+            }
+            if let Err(e) = notifier.send(&c, alert.kind, &msg).await {
+                errors.push(format!("{}: {}", c, e));
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(anyhow::format_err!(
+                "send {:?} alert error [{}]",
+                alert.kind,
+                errors.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn validate(cfg: &AlertRoutingConfig, notifier: &dyn Notifier) -> anyhow::Result<()> {
+    for r in cfg.routes.iter() {
+        if r.channels.is_empty() {
+            return Err(anyhow::format_err!("empty channels in route {:?}", r.kind));
+        }
+        for c in r.channels.iter() {
+            if !notifier.has_channel(c) {
+                return Err(anyhow::format_err!(
+                    "notifier of channel {} not found, route {:?}",
+                    c,
+                    r.kind
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn route_matches(r: &AlertRouteConfig, alert: &Alert) -> bool {
+    if r.kind != alert.kind {
+        return false;
+    }
+    if !r.envs.is_empty() && !r.envs.iter().any(|e| e.eq(alert.env)) {
+        return false;
+    }
+    if r.tokens.is_empty() && r.min_abs_asset_change <= Decimal::ZERO {
+        return true;
+    }
+
+    // at least one asset has to pass both filters
+    alert.assets.iter().any(|(asset, change)| {
+        (r.tokens.is_empty() || r.tokens.contains(asset)) && change.abs() >= r.min_abs_asset_change
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use cex_dex_monitor::{AlertKind, AlertRouteConfig, AlertRoutingConfig};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    use std::sync::Arc;
+
+    use super::{Alert, Router, SilenceTarget};
+    use crate::{scheduler, slackclient::client::Client};
+
+    fn route(kind: AlertKind, channel: &str) -> AlertRouteConfig {
+        AlertRouteConfig {
+            kind,
+            envs: Vec::new(),
+            channels: vec![channel.to_string()],
+            min_abs_asset_change: Decimal::ZERO,
+            tokens: Vec::new(),
+        }
+    }
+
+    fn router(routes: Vec<AlertRouteConfig>) -> Router {
+        let mut slack = Client::new();
+        for c in ["a", "b", "c"] {
+            slack.add_webhook(c.to_string(), format!("http://localhost/{}", c));
+        }
+        Router::new(&AlertRoutingConfig { routes }, Arc::new(slack))
+    }
+
+    #[test]
+    fn default_routing_keeps_errors_silent() {
+        let mut slack = Client::new();
+        slack.add_webhook(
+            "alert-virtual-taker-1".to_string(),
+            "http://localhost".to_string(),
+        );
+        let r = Router::new(&AlertRoutingConfig::default(), Arc::new(slack));
+
+        assert_eq!(
+            r.channels(&Alert::new(AlertKind::StateDone, "prod")),
+            vec!["alert-virtual-taker-1".to_string()]
+        );
+        assert!(r.channels(&Alert::new(AlertKind::Error, "prod")).is_empty());
+    }
+
+    #[test]
+    fn routes_filter_by_kind_env_and_assets() {
+        let mut prod_only = route(AlertKind::BalanceDiff1h, "b");
+        prod_only.envs = vec!["prod".to_string()];
+        let mut big_knc = route(AlertKind::BalanceDiff1h, "c");
+        big_knc.tokens = vec!["KNC".to_string()];
+        big_knc.min_abs_asset_change = dec!(100);
+
+        let r = router(vec![
+            route(AlertKind::BalanceDiff1h, "a"),
+            prod_only,
+            big_knc,
+            route(AlertKind::BalanceDiff24h, "a"),
+        ]);
+
+        let small = Alert::new(AlertKind::BalanceDiff1h, "dev").with_assets(vec![
+            ("KNC".to_string(), dec!(-5)),
+            ("USDT".to_string(), dec!(500)),
+        ]);
+        assert_eq!(r.channels(&small), vec!["a".to_string()]);
+
+        let big = Alert::new(AlertKind::BalanceDiff1h, "prod")
+            .with_assets(vec![("KNC".to_string(), dec!(-150))]);
+        assert_eq!(
+            r.channels(&big),
+            vec!["a".to_string(), "b".to_string(), "c".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn silenced_alerts_are_not_sent() {
+        let r = router(vec![route(AlertKind::Error, "a")]);
+        let now = scheduler::now();
+        r.silences().silence(
+            SilenceTarget::Env("prod".to_string()),
+            now + chrono::Duration::hours(1),
+        );
+        r.silences().silence(
+            SilenceTarget::Channel("b".to_string()),
+            now - chrono::Duration::minutes(1),
+        );
+
+        // the webhook of a is not reachable, only a skipped send succeeds
+        assert!(r
+            .send(&Alert::new(AlertKind::Error, "prod"), "down".to_string())
+            .await
+            .is_ok());
+        assert_eq!(
+            r.silences().active(now),
+            vec![(
+                SilenceTarget::Env("prod".to_string()),
+                now + chrono::Duration::hours(1)
+            )]
+        );
+    }
+
+    #[test]
+    fn reload_swaps_routes_of_every_clone() {
+        let r = router(vec![route(AlertKind::Error, "a")]);
+        let clone = r.clone();
+
+        let mut slack = Client::new();
+        slack.add_webhook("d".to_string(), "http://localhost/d".to_string());
+        let invalid = AlertRoutingConfig {
+            routes: vec![route(AlertKind::Error, "a")],
+        };
+        assert!(r.reload(&invalid, Arc::new(slack.clone())).is_err());
+        assert_eq!(
+            clone.channels(&Alert::new(AlertKind::Error, "prod")),
+            vec!["a".to_string()]
+        );
+
+        let valid = AlertRoutingConfig {
+            routes: vec![route(AlertKind::Error, "d")],
+        };
+        r.reload(&valid, Arc::new(slack)).unwrap();
+        assert_eq!(
+            clone.channels(&Alert::new(AlertKind::Error, "prod")),
+            vec!["d".to_string()]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn unknown_channel_is_rejected() {
+        router(vec![route(AlertKind::Error, "not-configured")]);
+    }
+}
@@ -0,0 +1,120 @@
+use std::{cell::Cell, time::Duration};
+
+use cex_dex_monitor::IntervalConfig;
+use rand::Rng;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Interval {
+    period: Duration,
+    jitter: Duration,
+}
+
+impl Interval {
+    pub fn new(period: Duration, jitter: Duration) -> Interval {
+        Interval { period, jitter }
+    }
+
+    // period plus a random part of the jitter, so the monitors do not hit the api together
+    pub fn next_delay(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return self.period;
+        }
+        let jitter_ms = rand::thread_rng().gen_range(0..=self.jitter.as_millis() as u64);
+        self.period + Duration::from_millis(jitter_ms)
+    }
+}
+
+impl From<&IntervalConfig> for Interval {
+    fn from(cfg: &IntervalConfig) -> Self {
+        Interval::new(
+            Duration::from_secs(cfg.period_secs),
+            Duration::from_secs(cfg.jitter_secs),
+        )
+    }
+}
+
+// sleep for the next delay of the interval, false when the shutdown came first
+pub async fn sleep(interval: &Interval, shutdown: &CancellationToken) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(interval.next_delay()) => true,
+        _ = shutdown.cancelled() => false,
+    }
+}
+
+type Anchor = (chrono::DateTime<chrono::Utc>, tokio::time::Instant);
+
+thread_local! {
+    static REPLAY_START: Cell<Option<Anchor>> = const { Cell::new(None) };
+}
+
+// the utc clock, in replays and tests a wall clock driven by the tokio clock, so a
+// paused runtime also pauses the monitors' time
+pub fn now() -> chrono::DateTime<chrono::Utc> {
+    match REPLAY_START.with(|s| s.get()).or_else(test_anchor) {
+        None => chrono::Utc::now(),
+        Some((start_utc, start_instant)) => {
+            let elapsed = tokio::time::Instant::now().saturating_duration_since(start_instant);
+            start_utc + chrono::Duration::from_std(elapsed).unwrap_or_default()
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_anchor() -> Option<Anchor> {
+    static START: std::sync::OnceLock<Anchor> = std::sync::OnceLock::new();
+    Some(*START.get_or_init(|| (chrono::Utc::now(), tokio::time::Instant::now())))
+}
+
+#[cfg(not(test))]
+fn test_anchor() -> Option<Anchor> {
+    None
+}
+
+// now() of this thread starts over at t, a replay runs every monitor on a single thread runtime
+pub fn start_at(t: chrono::DateTime<chrono::Utc>) {
+    REPLAY_START.with(|s| s.set(Some((t, tokio::time::Instant::now()))));
+}
+
+// resolves on the first SIGINT or SIGTERM
+pub async fn shutdown_signal() {
+    let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("install SIGTERM handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("received SIGINT"),
+        _ = sigterm.recv() => info!("received SIGTERM"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::{now, sleep, Interval};
+
+    #[test]
+    fn delay_stays_within_jitter() {
+        let i = Interval::new(Duration::from_secs(10), Duration::from_secs(5));
+        for _ in 0..100 {
+            let d = i.next_delay();
+            assert!(d >= Duration::from_secs(10) && d <= Duration::from_secs(15));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clock_follows_paused_runtime() {
+        let start = now();
+        let token = CancellationToken::new();
+        let i = Interval::new(Duration::from_secs(3600), Duration::ZERO);
+
+        assert!(sleep(&i, &token).await);
+        assert_eq!((now() - start).num_seconds(), 3600);
+
+        token.cancel();
+        assert!(!sleep(&i, &token).await);
+        assert_eq!((now() - start).num_seconds(), 3600);
+    }
+}
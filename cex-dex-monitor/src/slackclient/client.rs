@@ -4,12 +4,18 @@ use std::{
     collections::HashMap,
     error::{self, Error},
     fmt,
+    time::Duration,
 };
 
 use reqwest::header::CONTENT_TYPE;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 
-#[derive(Clone)]
+use super::message::Message;
+
+// of a webhook post, as the other notifiers
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Default)]
 pub struct Client {
     client: reqwest::Client,
     webhooks: HashMap<String, String>,
@@ -17,17 +23,23 @@ pub struct Client {
 
 impl Client {
     pub fn new() -> Client {
-        Client {
-            client: reqwest::Client::new(),
-            webhooks: HashMap::new(),
-        }
+        Client::default()
     }
 
     pub fn add_webhook(&mut self, channel: String, web_hook: String) {
         self.webhooks.insert(channel, web_hook);
     }
 
+    pub fn has_channel(&self, channel: &str) -> bool {
+        self.webhooks.contains_key(channel)
+    }
+
     pub async fn send_message(&self, channel: String, msg: String) -> Result<(), Box<dyn Error>> {
+        self.send(channel, &Message::plain(msg)).await
+    }
+
+    // send a Block Kit message, msg.text is the fallback
+    pub async fn send(&self, channel: String, msg: &Message) -> Result<(), Box<dyn Error>> {
         // get webhook urlBox
         let webhook = self.webhooks.get(&channel);
         if webhook.is_none() {
@@ -36,8 +48,7 @@ impl Client {
         let webhook = webhook.unwrap();
 
         // craft json message
-        let req = SendRequest { text: msg };
-        let req_text = serde_json::to_string(&req)?;
+        let req_text = serde_json::to_string(msg)?;
 
         // send message
         let resp = self
@@ -45,6 +56,7 @@ impl Client {
             .post(webhook)
             .header(CONTENT_TYPE, "application/json")
             .body(req_text)
+            .timeout(TIMEOUT)
             .send()
             .await?;
         if !resp.status().is_success() {
@@ -68,13 +80,6 @@ pub struct WebhookConfig {
     pub webhook: String,
 }
 
-// dto
-
-#[derive(Serialize)]
-struct SendRequest {
-    text: String,
-}
-
 // errors
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,264 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+// limits from https://api.slack.com/reference/block-kit/blocks
+const MAX_HEADER_LEN: usize = 150;
+const MAX_TEXT_LEN: usize = 3000;
+const MAX_SECTION_FIELDS: usize = 10;
+
+const COLOR_POSITIVE: &str = "#2eb886";
+const COLOR_NEGATIVE: &str = "#e01e5a";
+
+// Message is a Block Kit payload, text is the plain version shown in notifications
+// and by clients that can not render blocks
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Message {
+    pub text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<Block>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+}
+
+impl Message {
+    pub fn plain(text: String) -> Message {
+        Message {
+            text,
+            blocks: Vec::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    pub fn builder(fallback: String) -> Builder {
+        Builder {
+            msg: Message::plain(fallback),
+        }
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Message::plain(text)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Header {
+        text: Text,
+    },
+    Section {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        text: Option<Text>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        fields: Vec<Text>,
+    },
+    Context {
+        elements: Vec<Text>,
+    },
+    Divider,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum Text {
+    #[serde(rename = "plain_text")]
+    Plain { text: String },
+    #[serde(rename = "mrkdwn")]
+    Markdown { text: String },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub color: String,
+    pub blocks: Vec<Block>,
+}
+
+pub struct Builder {
+    msg: Message,
+}
+
+impl Builder {
+    pub fn header(mut self, text: &str) -> Builder {
+        self.msg.blocks.push(Block::Header {
+            text: Text::Plain {
+                text: truncate(text, MAX_HEADER_LEN),
+            },
+        });
+        self
+    }
+
+    pub fn markdown(mut self, text: &str) -> Builder {
+        if text.is_empty() {
+            return self;
+        }
+        self.msg.blocks.push(markdown_section(text));
+        self
+    }
+
+    pub fn context(mut self, text: &str) -> Builder {
+        self.msg.blocks.push(Block::Context {
+            elements: vec![Text::Markdown {
+                text: truncate(text, MAX_TEXT_LEN),
+            }],
+        });
+        self
+    }
+
+    pub fn divider(mut self) -> Builder {
+        self.msg.blocks.push(Block::Divider);
+        self
+    }
+
+    // label/value pairs, split into several sections when over the fields limit
+    pub fn fields(mut self, fields: Vec<(&str, String)>) -> Builder {
+        for chunk in fields.chunks(MAX_SECTION_FIELDS) {
+            self.msg.blocks.push(Block::Section {
+                text: None,
+                fields: chunk
+                    .iter()
+                    .map(|(label, value)| Text::Markdown {
+                        text: truncate(&format!("*{}*\n{}", label, value), MAX_TEXT_LEN),
+                    })
+                    .collect(),
+            });
+        }
+        self
+    }
+
+    // one line per tx: hash (linked when the explorer is known) and status
+    pub fn tx_links(
+        self,
+        title: &str,
+        explorer_tx_url: Option<&str>,
+        txs: &[(String, String)],
+    ) -> Builder {
+        if txs.is_empty() {
+            return self;
+        }
+
+        let mut text = format!("*{}*\n", title);
+        for (hash, status) in txs.iter() {
+            match explorer_tx_url {
+                None => text.push_str(&format!("`{}`: {}\n", hash, status)),
+                Some(url) => text.push_str(&format!(
+                    "<{}{}|{}>: {}\n",
+                    url,
+                    hash,
+                    short_hash(hash),
+                    status
+                )),
+            }
+        }
+        self.markdown(&text)
+    }
+
+    // asset rows grouped by sign into green and red attachments
+    pub fn diff_rows(mut self, title: &str, rows: &[(String, Decimal)]) -> Builder {
+        for (color, positive) in [(COLOR_POSITIVE, true), (COLOR_NEGATIVE, false)] {
+            let lines = rows
+                .iter()
+                .filter(|(_, v)| !v.is_zero() && v.is_sign_positive() == positive)
+                .map(|(asset, v)| format!("`{}`: {:+}", asset, v))
+                .collect::<Vec<String>>();
+            if lines.is_empty() {
+                continue;
+            }
+            self.msg.attachments.push(Attachment {
+                color: color.to_string(),
+                blocks: vec![markdown_section(&format!(
+                    "*{}*\n{}",
+                    title,
+                    lines.join("\n")
+                ))],
+            });
+        }
+        self
+    }
+
+    pub fn build(self) -> Message {
+        self.msg
+    }
+}
+
+fn markdown_section(text: &str) -> Block {
+    Block::Section {
+        text: Some(Text::Markdown {
+            text: truncate(text, MAX_TEXT_LEN),
+        }),
+        fields: Vec::new(),
+    }
+}
+
+fn short_hash(hash: &str) -> String {
+    if hash.len() <= 14 || !hash.is_ascii() {
+        return hash.to_string();
+    }
+    format!("{}…{}", &hash[..8], &hash[hash.len() - 6..])
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut s: String = text.chars().take(max_chars - 1).collect();
+    s.push('…');
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::Message;
+
+    #[test]
+    fn plain_message_has_text_only() {
+        let v = serde_json::to_value(Message::plain("hello".to_string())).unwrap();
+        assert_eq!(v, serde_json::json!({"text": "hello"}));
+    }
+
+    #[test]
+    fn builder_emits_blocks_and_colored_rows() {
+        let msg = Message::builder("fallback".to_string())
+            .header("STATE DONE")
+            .fields(vec![("ENV", "prod".to_string())])
+            .tx_links(
+                "TXs",
+                Some("https://polygonscan.com/tx/"),
+                &[("0x0123456789abcdef".to_string(), "EXECUTED".to_string())],
+            )
+            .diff_rows(
+                "ASSET CHANGES",
+                &[
+                    ("KNC".to_string(), dec!(1.5)),
+                    ("USDT".to_string(), dec!(-2)),
+                ],
+            )
+            .build();
+
+        let v = serde_json::to_value(&msg).unwrap();
+        assert_eq!(v["text"], "fallback");
+        assert_eq!(v["blocks"][0]["type"], "header");
+        assert_eq!(v["blocks"][0]["text"]["type"], "plain_text");
+        assert_eq!(v["blocks"][1]["fields"][0]["text"], "*ENV*\nprod");
+        assert_eq!(
+            v["blocks"][2]["text"]["text"],
+            "*TXs*\n<https://polygonscan.com/tx/0x0123456789abcdef|0x012345…abcdef>: EXECUTED\n"
+        );
+        assert_eq!(v["attachments"][0]["color"], "#2eb886");
+        assert_eq!(
+            v["attachments"][0]["blocks"][0]["text"]["text"],
+            "*ASSET CHANGES*\n`KNC`: +1.5"
+        );
+        assert_eq!(v["attachments"][1]["color"], "#e01e5a");
+    }
+
+    #[test]
+    fn fields_are_split_by_section_limit() {
+        let fields = (0..12).map(|i| ("F", i.to_string())).collect();
+        let msg = Message::builder(String::new()).fields(fields).build();
+        assert_eq!(msg.blocks.len(), 2);
+    }
+}
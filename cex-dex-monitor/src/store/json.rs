@@ -0,0 +1,74 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use super::{BalanceSnapshot, Document, SnapshotWindow, Store};
+
+// JsonFileStore keeps the whole document in memory and rewrites the file on every save
+pub struct JsonFileStore {
+    path: PathBuf,
+    doc: Mutex<Document>,
+}
+
+impl JsonFileStore {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<JsonFileStore> {
+        let path = path.as_ref().to_path_buf();
+        let doc = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).map_err(|e| {
+                anyhow::format_err!("parse state store {} error {}", path.display(), e)
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Document::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(JsonFileStore {
+            path,
+            doc: Mutex::new(doc),
+        })
+    }
+
+    // write to a temp file then rename, a crash never leaves a truncated file
+    fn persist(&self, doc: &Document) -> anyhow::Result<()> {
+        let data = serde_json::to_vec(doc)?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, &self.path)?;
+
+        Ok(())
+    }
+}
+
+impl Store for JsonFileStore {
+    fn load_seen_states(&self, scope: &str) -> anyhow::Result<Option<HashSet<String>>> {
+        Ok(self.doc.lock().unwrap().seen_states(scope))
+    }
+
+    fn save_seen_states(&self, scope: &str, state_ids: &HashSet<String>) -> anyhow::Result<()> {
+        let mut doc = self.doc.lock().unwrap();
+        doc.set_seen_states(scope, state_ids);
+        self.persist(&doc)
+    }
+
+    fn load_balance_snapshot(
+        &self,
+        env: &str,
+        window: SnapshotWindow,
+    ) -> anyhow::Result<Option<BalanceSnapshot>> {
+        Ok(self.doc.lock().unwrap().balance_snapshot(env, window))
+    }
+
+    fn save_balance_snapshot(
+        &self,
+        env: &str,
+        window: SnapshotWindow,
+        snapshot: &BalanceSnapshot,
+    ) -> anyhow::Result<()> {
+        let mut doc = self.doc.lock().unwrap();
+        doc.set_balance_snapshot(env, window, snapshot);
+        self.persist(&doc)
+    }
+}
@@ -0,0 +1,48 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use super::{BalanceSnapshot, Document, SnapshotWindow, Store};
+
+// MemoryStore keeps everything in the process, restarts re-seed from scratch
+pub struct MemoryStore {
+    doc: Mutex<Document>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore {
+            doc: Mutex::new(Document::default()),
+        }
+    }
+}
+
+impl Store for MemoryStore {
+    fn load_seen_states(&self, scope: &str) -> anyhow::Result<Option<HashSet<String>>> {
+        Ok(self.doc.lock().unwrap().seen_states(scope))
+    }
+
+    fn save_seen_states(&self, scope: &str, state_ids: &HashSet<String>) -> anyhow::Result<()> {
+        self.doc.lock().unwrap().set_seen_states(scope, state_ids);
+        Ok(())
+    }
+
+    fn load_balance_snapshot(
+        &self,
+        env: &str,
+        window: SnapshotWindow,
+    ) -> anyhow::Result<Option<BalanceSnapshot>> {
+        Ok(self.doc.lock().unwrap().balance_snapshot(env, window))
+    }
+
+    fn save_balance_snapshot(
+        &self,
+        env: &str,
+        window: SnapshotWindow,
+        snapshot: &BalanceSnapshot,
+    ) -> anyhow::Result<()> {
+        self.doc
+            .lock()
+            .unwrap()
+            .set_balance_snapshot(env, window, snapshot);
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+pub mod json;
+pub mod memory;
+mod test;
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use cex_dex_monitor::{StateStoreConfig, StateStoreKind};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+// Store keeps what the monitors have to remember across restarts: the state ids
+// already notified per monitored url and the balance snapshots used as diff baselines.
+pub trait Store: Send + Sync {
+    // returns None when nothing was ever saved for the scope
+    fn load_seen_states(&self, scope: &str) -> anyhow::Result<Option<HashSet<String>>>;
+    fn save_seen_states(&self, scope: &str, state_ids: &HashSet<String>) -> anyhow::Result<()>;
+
+    fn load_balance_snapshot(
+        &self,
+        env: &str,
+        window: SnapshotWindow,
+    ) -> anyhow::Result<Option<BalanceSnapshot>>;
+    fn save_balance_snapshot(
+        &self,
+        env: &str,
+        window: SnapshotWindow,
+        snapshot: &BalanceSnapshot,
+    ) -> anyhow::Result<()>;
+}
+
+pub fn from_config(cfg: &StateStoreConfig) -> anyhow::Result<Arc<dyn Store>> {
+    match cfg.kind {
+        StateStoreKind::Json => Ok(Arc::new(json::JsonFileStore::open(&cfg.path)?)),
+        StateStoreKind::Memory => Ok(Arc::new(memory::MemoryStore::new())),
+    }
+}
+
+// scope of the seen states of one monitored url
+pub fn seen_states_scope(env: &str, base_url: &str) -> String {
+    format!("{}|{}", env, base_url)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotWindow {
+    Hour,
+    Day,
+}
+
+impl SnapshotWindow {
+    fn key(&self, env: &str) -> String {
+        match self {
+            SnapshotWindow::Hour => format!("{}|1h", env),
+            SnapshotWindow::Day => format!("{}|24h", env),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BalanceSnapshot {
+    pub balances: HashMap<String, Decimal>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    // usd value of the balances when taken, missing before the valuation was added
+    #[serde(default)]
+    pub usd_value: Option<Decimal>,
+}
+
+// content shared by the store implementations
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct Document {
+    #[serde(default)]
+    seen_states: HashMap<String, HashSet<String>>,
+    #[serde(default)]
+    balance_snapshots: HashMap<String, BalanceSnapshot>,
+}
+
+impl Document {
+    fn seen_states(&self, scope: &str) -> Option<HashSet<String>> {
+        self.seen_states.get(scope).cloned()
+    }
+
+    fn set_seen_states(&mut self, scope: &str, state_ids: &HashSet<String>) {
+        self.seen_states
+            .insert(scope.to_string(), state_ids.clone());
+    }
+
+    fn balance_snapshot(&self, env: &str, window: SnapshotWindow) -> Option<BalanceSnapshot> {
+        self.balance_snapshots.get(&window.key(env)).cloned()
+    }
+
+    fn set_balance_snapshot(&mut self, env: &str, window: SnapshotWindow, s: &BalanceSnapshot) {
+        self.balance_snapshots.insert(window.key(env), s.clone());
+    }
+}
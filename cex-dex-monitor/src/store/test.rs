@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, env, fs};
+
+    use rust_decimal_macros::dec;
+
+    use crate::store::{json::JsonFileStore, BalanceSnapshot, SnapshotWindow, Store};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        env::temp_dir().join(format!(
+            "cex-dex-monitor-{}-{}-{}.json",
+            name,
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ))
+    }
+
+    #[test]
+    fn json_store_survives_reopen() {
+        let path = temp_path("reopen");
+
+        let state_ids: HashSet<String> = ["a".to_string(), "b".to_string()].into();
+        let snapshot = BalanceSnapshot {
+            balances: [
+                ("USDT".to_string(), dec!(10.5)),
+                ("KNC".to_string(), dec!(3)),
+            ]
+            .into(),
+            updated_at: chrono::Utc::now(),
+            usd_value: Some(dec!(16.5)),
+        };
+        {
+            let store = JsonFileStore::open(&path).unwrap();
+            assert!(store.load_seen_states("prod|x").unwrap().is_none());
+            store.save_seen_states("prod|x", &state_ids).unwrap();
+            store
+                .save_balance_snapshot("prod", SnapshotWindow::Day, &snapshot)
+                .unwrap();
+        }
+
+        let store = JsonFileStore::open(&path).unwrap();
+        assert_eq!(store.load_seen_states("prod|x").unwrap(), Some(state_ids));
+        assert!(store.load_seen_states("prod|y").unwrap().is_none());
+        assert_eq!(
+            store
+                .load_balance_snapshot("prod", SnapshotWindow::Day)
+                .unwrap(),
+            Some(snapshot)
+        );
+        assert!(store
+            .load_balance_snapshot("prod", SnapshotWindow::Hour)
+            .unwrap()
+            .is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn json_store_rejects_corrupted_file() {
+        let path = temp_path("corrupted");
+        fs::write(&path, "{not json").unwrap();
+
+        assert!(JsonFileStore::open(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
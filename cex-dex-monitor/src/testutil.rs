@@ -0,0 +1,212 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::cexdexclient::dto::{CexOrderData, DexTxData, StateData};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, StatusCode,
+};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+// MockServer is a local http server answering scripted responses per path
+pub struct MockServer {
+    pub url: String,
+    routes: Arc<Mutex<HashMap<String, Route>>>,
+}
+
+#[derive(Default)]
+struct Route {
+    // the last response is repeated once the others are consumed
+    responses: VecDeque<MockResponse>,
+    requests: Vec<String>,
+    queries: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub body: String,
+    pub delay: Duration,
+}
+
+pub fn ok(body: &str) -> MockResponse {
+    MockResponse {
+        status: 200,
+        body: body.to_string(),
+        delay: Duration::ZERO,
+    }
+}
+
+pub fn status(status: u16) -> MockResponse {
+    MockResponse {
+        status,
+        body: String::new(),
+        delay: Duration::ZERO,
+    }
+}
+
+impl MockResponse {
+    pub fn delayed(mut self, delay: Duration) -> MockResponse {
+        self.delay = delay;
+        self
+    }
+}
+
+impl MockServer {
+    pub async fn start() -> MockServer {
+        let routes = Arc::new(Mutex::new(HashMap::<String, Route>::new()));
+
+        let svc_routes = routes.clone();
+        let make_svc = make_service_fn(move |_| {
+            let routes = svc_routes.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(routes.clone(), req))) }
+        });
+        let server = hyper::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_svc);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        MockServer { url, routes }
+    }
+
+    pub fn respond(&self, path: &str, responses: Vec<MockResponse>) {
+        let mut routes = self.routes.lock().unwrap();
+        let route = routes.entry(path.to_string()).or_default();
+        route.responses = responses.into();
+    }
+
+    pub fn hits(&self, path: &str) -> usize {
+        self.requests(path).len()
+    }
+
+    // bodies of the requests received on path
+    pub fn requests(&self, path: &str) -> Vec<String> {
+        match self.routes.lock().unwrap().get(path) {
+            None => Vec::new(),
+            Some(r) => r.requests.clone(),
+        }
+    }
+
+    // query strings of the requests received on path
+    pub fn queries(&self, path: &str) -> Vec<String> {
+        match self.routes.lock().unwrap().get(path) {
+            None => Vec::new(),
+            Some(r) => r.queries.clone(),
+        }
+    }
+}
+
+async fn handle(
+    routes: Arc<Mutex<HashMap<String, Route>>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let path = req.uri().path().to_string();
+    let query = req.uri().query().unwrap_or_default().to_string();
+    let body = hyper::body::to_bytes(req.into_body())
+        .await
+        .map(|b| String::from_utf8_lossy(&b).to_string())
+        .unwrap_or_default();
+
+    let resp = {
+        let mut routes = routes.lock().unwrap();
+        let route = routes.entry(path).or_default();
+        route.requests.push(body);
+        route.queries.push(query);
+        match route.responses.len() {
+            0 => status(404),
+            1 => route.responses[0].clone(),
+            _ => route.responses.pop_front().unwrap(),
+        }
+    };
+
+    if !resp.delay.is_zero() {
+        tokio::time::sleep(resp.delay).await;
+    }
+    let mut r = Response::new(Body::from(resp.body));
+    *r.status_mut() = StatusCode::from_u16(resp.status).unwrap();
+    Ok(r)
+}
+
+// fixtures
+
+pub fn order(fee_asset: &str, fee_amount: Decimal) -> CexOrderData {
+    CexOrderData {
+        id: "o".to_string(),
+        status: "FILLED".to_string(),
+        base_symbol: "KNC".to_string(),
+        quote_symbol: "USDT".to_string(),
+        side: "BUY".to_string(),
+        actual_price: 2.0,
+        actual_price_with_fee: 2.0,
+        base_amount: dec!(10),
+        filled_base_amount: dec!(10),
+        filled_quote_amount: dec!(20),
+        fee_asset: fee_asset.to_string(),
+        fee_amount,
+        filled_at: 0,
+        created_time: 0,
+    }
+}
+
+pub fn tx() -> DexTxData {
+    DexTxData {
+        nonce: 1,
+        tx_hash: "0x1".to_string(),
+        status: "EXECUTED".to_string(),
+        to_wallet: String::new(),
+        router_address: String::new(),
+        token_in: "KNC".to_string(),
+        token_out: "USDT".to_string(),
+        amount_in: dec!(10),
+        estimated_amount_out: dec!(21),
+        actual_amount_out: dec!(21),
+        estimated_price: 2.1,
+        estimated_price_with_fee: 2.1,
+        actual_price: 2.1,
+        actual_price_with_fee: 2.1,
+        // 100k gas at 100 gwei, native token at 0.5 USDT
+        gas_price: 100.0,
+        gas_used: 100_000,
+        max_tip: 0.0,
+        estimated_at: 0,
+        broadcasted_at: 0,
+        mined_at: 0,
+        mined_block: 0,
+        native_token_price_in_quote: 0.5,
+    }
+}
+
+// a finished KNC buy with fees in USDT, KNC and BNB
+pub fn state() -> StateData {
+    StateData {
+        state_id: "s1".to_string(),
+        cex: "binance".to_string(),
+        dex: "kyberswap".to_string(),
+        dex_chain: "polygon".to_string(),
+        dex_wallet: String::new(),
+        token: "KNC".to_string(),
+        base_amount: dec!(10),
+        side: "BUY".to_string(),
+        p1_price_diff: 0.0,
+        p1_profitable_threshold: 0.0,
+        p1_fillable_threshold: 0.0,
+        p2_cancel_threshold: 0.0,
+        is_done: true,
+        created_time: "2024-01-18T10:00:00Z".to_string(),
+        p2_total_gas: 0.0,
+        slippage: 0.0,
+        p1_cex_orders: Some(vec![order("USDT", dec!(0.02))]),
+        p2_cex_orders: Some(vec![order("KNC", dec!(0.01)), order("BNB", dec!(0.001))]),
+        p2_dex_txs: Some(vec![tx()]),
+        asset_change: Some(HashMap::from([
+            ("USDT".to_string(), dec!(1)),
+            ("KNC".to_string(), dec!(0.1)),
+        ])),
+        asset_change_with_fee: None,
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+};
+
+use chrono::{DateTime, Utc};
+use plotters::{prelude::*, style::register_font};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+pub const SPARKLINE_WIDTH: usize = 28;
+
+const SIZE: (u32, u32) = (1024, 480);
+const FONT: &str = "sans-serif";
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+pub fn write_csv<W: Write>(w: W, series: &[(DateTime<Utc>, Decimal)]) -> anyhow::Result<()> {
+    let mut w = csv::Writer::from_writer(w);
+    w.write_record(["time", "amount"])?;
+    for (at, amount) in series {
+        w.write_record([at.to_rfc3339(), amount.to_string()])?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+// the texts of the charts need a font, it is loaded once per process
+pub fn load_font(path: &str) -> anyhow::Result<()> {
+    static LOADED: OnceLock<Result<(), String>> = OnceLock::new();
+    LOADED
+        .get_or_init(|| {
+            let bytes = std::fs::read(path).map_err(|e| format!("read font {}: {}", path, e))?;
+            register_font(FONT, FontStyle::Normal, Box::leak(bytes.into_boxed_slice()))
+                .map_err(|_| format!("invalid font {}", path))
+        })
+        .clone()
+        .map_err(|e| anyhow::format_err!(e))
+}
+
+pub fn svg(title: &str, series: &[(DateTime<Utc>, Decimal)]) -> anyhow::Result<String> {
+    let mut buf = String::new();
+    draw(
+        SVGBackend::with_string(&mut buf, SIZE).into_drawing_area(),
+        title,
+        series,
+    )?;
+    Ok(buf)
+}
+
+pub fn png(path: &str, title: &str, series: &[(DateTime<Utc>, Decimal)]) -> anyhow::Result<()> {
+    draw(
+        BitMapBackend::new(path, SIZE).into_drawing_area(),
+        title,
+        series,
+    )
+}
+
+// the png encoder of plotters only writes files, it goes through a temp file
+pub fn png_bytes(title: &str, series: &[(DateTime<Utc>, Decimal)]) -> anyhow::Result<Vec<u8>> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "cex-dex-monitor-chart-{}-{}.png",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let path = path.to_string_lossy().to_string();
+    let bytes = png(&path, title, series).and_then(|_| Ok(std::fs::read(&path)?));
+    let _ = std::fs::remove_file(&path);
+    bytes
+}
+
+fn draw<DB: DrawingBackend>(
+    root: DrawingArea<DB, plotters::coord::Shift>,
+    title: &str,
+    series: &[(DateTime<Utc>, Decimal)],
+) -> anyhow::Result<()> {
+    let points = series
+        .iter()
+        .map(|(at, amount)| (at.timestamp(), amount.to_f64().unwrap_or_default()))
+        .collect::<Vec<(i64, f64)>>();
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(f), Some(l)) => (f.0, l.0.max(f.0 + 1)),
+        _ => anyhow::bail!("no balance in the range"),
+    };
+    let min = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let max = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+    // a flat line is drawn in the middle
+    let pad = if max > min {
+        (max - min) * 0.05
+    } else {
+        min.abs().max(1.0) * 0.05
+    };
+
+    let err = |e: DrawingAreaErrorKind<DB::ErrorType>| anyhow::format_err!("draw chart: {}", e);
+    root.fill(&WHITE).map_err(err)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, (FONT, 20))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(80)
+        .build_cartesian_2d(first..last, (min - pad)..(max + pad))
+        .map_err(err)?;
+    chart
+        .configure_mesh()
+        .x_labels(8)
+        .x_label_formatter(&|t| {
+            DateTime::from_timestamp(*t, 0)
+                .map(|t| t.format("%m-%d %H:%M").to_string())
+                .unwrap_or_default()
+        })
+        .draw()
+        .map_err(err)?;
+    chart
+        .draw_series(LineSeries::new(points, &BLUE))
+        .map_err(err)?;
+    root.present().map_err(err)?;
+    Ok(())
+}
+
+// the last balance of each of the width equal periods of [from, to), a period
+// without a balance keeps the previous one
+pub fn buckets(
+    series: &[(DateTime<Utc>, Decimal)],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    width: usize,
+) -> Vec<Option<Decimal>> {
+    let span = (to - from).num_seconds().max(1);
+    let mut buckets = vec![None; width];
+    for (at, amount) in series {
+        let offset = (*at - from).num_seconds();
+        if offset < 0 || offset >= span {
+            continue;
+        }
+        buckets[(offset * width as i64 / span) as usize] = Some(*amount);
+    }
+    for i in 1..width {
+        if buckets[i].is_none() {
+            buckets[i] = buckets[i - 1];
+        }
+    }
+    buckets
+}
+
+// one bar per bucket scaled between the min and the max, a space before the first balance
+pub fn sparkline(buckets: &[Option<Decimal>]) -> String {
+    let values = buckets.iter().flatten();
+    let (min, max) = match (values.clone().min(), values.max()) {
+        (Some(min), Some(max)) => (*min, *max),
+        _ => return String::new(),
+    };
+    let top = Decimal::from(BARS.len() - 1);
+    buckets
+        .iter()
+        .map(|b| match b {
+            None => ' ',
+            Some(_) if max == min => BARS[BARS.len() / 2],
+            Some(v) => {
+                let level = ((v - min) * top / (max - min)).round().to_usize();
+                BARS[level.unwrap_or_default().min(BARS.len() - 1)]
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,286 @@
+pub mod chart;
+mod test;
+pub mod upload;
+
+use std::{collections::HashMap, str::FromStr, sync::Arc, sync::Mutex, time::Duration};
+
+use cex_dex_monitor::{AlertKind, TimelineConfig};
+use chrono::{DateTime, Datelike, Utc};
+use rusqlite::{params, Connection};
+use rust_decimal::Decimal;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{
+    routing::{Alert, Router},
+    scheduler,
+    slackclient::message::Message,
+};
+
+// balances of one asset, oldest first
+pub type Series = Vec<(DateTime<Utc>, Decimal)>;
+
+// Timeline keeps every fetched aggregated balance of the monitored envs
+pub struct Timeline {
+    conn: Mutex<Connection>,
+}
+
+pub fn from_config(cfg: &TimelineConfig) -> anyhow::Result<Option<Arc<Timeline>>> {
+    if cfg.path.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Arc::new(Timeline::open(&cfg.path)?)))
+}
+
+impl Timeline {
+    pub fn open(path: &str) -> anyhow::Result<Timeline> {
+        Timeline::init(Connection::open(path)?)
+    }
+
+    pub fn in_memory() -> anyhow::Result<Timeline> {
+        Timeline::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> anyhow::Result<Timeline> {
+        // amounts are decimal strings to keep their precision
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS balances (
+                env TEXT NOT NULL,
+                asset TEXT NOT NULL,
+                at INTEGER NOT NULL,
+                amount TEXT NOT NULL,
+                PRIMARY KEY (env, asset, at)
+            )",
+        )?;
+        Ok(Timeline {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn append(
+        &self,
+        env: &str,
+        at: DateTime<Utc>,
+        balances: &HashMap<String, Decimal>,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT OR REPLACE INTO balances (env, asset, at, amount) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (asset, amount) in balances {
+                insert.execute(params![env, asset, at.timestamp(), amount.to_string()])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // balances of the asset in [from, to)
+    pub fn series(
+        &self,
+        env: &str,
+        asset: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> anyhow::Result<Series> {
+        let conn = self.conn.lock().unwrap();
+        let mut query = conn.prepare_cached(
+            "SELECT at, amount FROM balances
+             WHERE env = ?1 AND asset = ?2 AND at >= ?3 AND at < ?4
+             ORDER BY at",
+        )?;
+        let rows = query.query_map(params![env, asset, from.timestamp(), to.timestamp()], |r| {
+            Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?))
+        })?;
+
+        let mut series = Vec::new();
+        for r in rows {
+            let (at, amount) = r?;
+            let at = DateTime::from_timestamp(at, 0)
+                .ok_or_else(|| anyhow::format_err!("invalid time {}", at))?;
+            series.push((at, Decimal::from_str(&amount)?));
+        }
+        Ok(series)
+    }
+
+    // assets of the env with a balance in [from, to), sorted
+    pub fn assets(
+        &self,
+        env: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut query = conn.prepare_cached(
+            "SELECT DISTINCT asset FROM balances
+             WHERE env = ?1 AND at >= ?2 AND at < ?3
+             ORDER BY asset",
+        )?;
+        let rows = query.query_map(params![env, from.timestamp(), to.timestamp()], |r| {
+            r.get::<_, String>(0)
+        })?;
+        Ok(rows.collect::<Result<Vec<String>, _>>()?)
+    }
+
+    pub fn envs(&self) -> anyhow::Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut query = conn.prepare_cached("SELECT DISTINCT env FROM balances ORDER BY env")?;
+        let rows = query.query_map([], |r| r.get::<_, String>(0))?;
+        Ok(rows.collect::<Result<Vec<String>, _>>()?)
+    }
+}
+
+// monday 00:10 utc strictly after now
+pub fn next_weekly_run(now: DateTime<Utc>) -> DateTime<Utc> {
+    let today = now.date_naive();
+    let monday = today - chrono::Duration::days(i64::from(today.weekday().num_days_from_monday()));
+    let run = monday.and_hms_opt(0, 10, 0).unwrap().and_utc();
+    if run > now {
+        return run;
+    }
+    run + chrono::Duration::days(7)
+}
+
+// sends the balances of the past week of every env on monday 00:10 utc: png charts
+// uploaded with the bot token of cfg when set, otherwise a sparkline per asset through
+// the routes, as slack webhooks cannot upload images
+pub async fn run_weekly_chart(
+    timeline: Arc<Timeline>,
+    cfg: TimelineConfig,
+    router: Router,
+    shutdown: CancellationToken,
+) {
+    info!("weekly balance chart started");
+    let uploader = upload::Uploader::from_config(&cfg);
+    loop {
+        let next = next_weekly_run(scheduler::now());
+        let wait = (next - scheduler::now())
+            .to_std()
+            .unwrap_or(Duration::from_secs(60));
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = shutdown.cancelled() => {
+                info!("weekly balance chart stopped");
+                return;
+            }
+        }
+
+        let to = next.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let from = to - chrono::Duration::days(7);
+        if let Err(e) = send_weekly(&timeline, &cfg, uploader.as_ref(), &router, from, to).await {
+            warn!(error = %e, "weekly balance chart failed");
+        }
+    }
+}
+
+pub async fn send_weekly(
+    timeline: &Timeline,
+    cfg: &TimelineConfig,
+    uploader: Option<&upload::Uploader>,
+    router: &Router,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    for env in timeline.envs()? {
+        let mut rows = Vec::new();
+        for asset in timeline.assets(&env, from, to)? {
+            let series = timeline.series(&env, &asset, from, to)?;
+            rows.push((asset, series));
+        }
+        if rows.is_empty() {
+            continue;
+        }
+        if let Some(u) = uploader {
+            match upload_charts(u, cfg, &env, from, to, &rows).await {
+                Ok(_) => {
+                    info!(%env, "uploaded weekly balance charts");
+                    continue;
+                }
+                Err(e) => {
+                    warn!(%env, error = %e, "upload weekly balance charts failed, sending sparklines")
+                }
+            }
+        }
+        router
+            .send(
+                &Alert::new(AlertKind::BalanceChart, &env),
+                weekly_message(&env, from, to, &rows),
+            )
+            .await?;
+        info!(%env, "sent weekly balance chart");
+    }
+    Ok(())
+}
+
+async fn upload_charts(
+    uploader: &upload::Uploader,
+    cfg: &TimelineConfig,
+    env: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    rows: &[(String, Series)],
+) -> anyhow::Result<()> {
+    chart::load_font(&cfg.font_path)?;
+    let mut files = Vec::new();
+    for (asset, series) in rows {
+        let title = format!(
+            "{} {} {} - {}",
+            env,
+            asset,
+            from.date_naive(),
+            to.date_naive()
+        );
+        files.push(upload::File {
+            bytes: chart::png_bytes(&title, series)?,
+            filename: format!("{}-{}-{}.png", env, asset, from.date_naive()),
+            title,
+        });
+    }
+    uploader
+        .upload(&weekly_message(env, from, to, rows).text, &files)
+        .await
+}
+
+fn weekly_message(
+    env: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    rows: &[(String, Series)],
+) -> Message {
+    let period = format!(
+        "{} - {}",
+        from.date_naive(),
+        (to - chrono::Duration::days(1)).date_naive()
+    );
+    let mut lines = Vec::<String>::new();
+    for (asset, series) in rows {
+        let (first, last) = match (series.first(), series.last()) {
+            (Some(f), Some(l)) => (f.1, l.1),
+            _ => continue,
+        };
+        let change = last - first;
+        lines.push(format!(
+            "{} {} {} -> {} ({}{})",
+            asset,
+            chart::sparkline(&chart::buckets(series, from, to, chart::SPARKLINE_WIDTH)),
+            first,
+            last,
+            if change.is_sign_negative() { "" } else { "+" },
+            change
+        ));
+    }
+
+    let fallback = format!(
+        "*****\n*WEEKLY BALANCES*\n> ENV: {}\n> PERIOD: {}\n{}\n*****",
+        env,
+        period,
+        lines.join("\n")
+    );
+    Message::builder(fallback)
+        .header("WEEKLY BALANCES")
+        .fields(vec![("ENV", env.to_string()), ("PERIOD", period)])
+        .markdown(&format!("```\n{}\n```", lines.join("\n")))
+        .build()
+}
@@ -0,0 +1,212 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use cex_dex_monitor::{AlertRoutingConfig, TimelineConfig};
+    use chrono::{DateTime, Duration, Utc};
+    use rust_decimal_macros::dec;
+
+    use crate::{
+        replay::TextSink,
+        routing::Router,
+        testutil::{ok, MockServer},
+        timeline::{chart, next_weekly_run, send_weekly, upload::Uploader, Timeline},
+    };
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    #[test]
+    fn series_is_ranged_and_ordered() {
+        let t = Timeline::in_memory().unwrap();
+        for (time, usdt, knc) in [
+            ("2024-05-02T00:00:00Z", dec!(100.5), dec!(7)),
+            ("2024-05-01T00:00:00Z", dec!(99.25), dec!(8)),
+            ("2024-05-03T00:00:00Z", dec!(101), dec!(6)),
+        ] {
+            let balances: HashMap<String, _> =
+                [("USDT".to_string(), usdt), ("KNC".to_string(), knc)].into();
+            t.append("prod", at(time), &balances).unwrap();
+        }
+        t.append(
+            "dev",
+            at("2024-05-02T00:00:00Z"),
+            &[("ETH".to_string(), dec!(1))].into(),
+        )
+        .unwrap();
+
+        let series = t
+            .series(
+                "prod",
+                "USDT",
+                at("2024-05-01T00:00:00Z"),
+                at("2024-05-03T00:00:00Z"),
+            )
+            .unwrap();
+        assert_eq!(
+            series,
+            vec![
+                (at("2024-05-01T00:00:00Z"), dec!(99.25)),
+                (at("2024-05-02T00:00:00Z"), dec!(100.5)),
+            ]
+        );
+        assert_eq!(
+            t.assets(
+                "prod",
+                at("2024-05-01T00:00:00Z"),
+                at("2024-05-04T00:00:00Z")
+            )
+            .unwrap(),
+            vec!["KNC", "USDT"]
+        );
+        assert_eq!(t.envs().unwrap(), vec!["dev", "prod"]);
+    }
+
+    #[test]
+    fn csv_and_svg_export() {
+        let series = vec![
+            (at("2024-05-01T00:00:00Z"), dec!(99.25)),
+            (at("2024-05-01T01:00:00Z"), dec!(100.5)),
+        ];
+
+        let mut csv = Vec::new();
+        chart::write_csv(&mut csv, &series).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "time,amount\n2024-05-01T00:00:00+00:00,99.25\n2024-05-01T01:00:00+00:00,100.5\n"
+        );
+
+        chart::load_font(&cex_dex_monitor::TimelineConfig::default().font_path).unwrap();
+        let svg = chart::svg("prod USDT", &series).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("prod USDT"));
+
+        assert!(chart::svg("prod USDT", &[]).is_err());
+    }
+
+    #[test]
+    fn sparkline_scales_between_min_and_max() {
+        let from = at("2024-05-01T00:00:00Z");
+        let series = vec![
+            (from + Duration::hours(1), dec!(10)),
+            (from + Duration::hours(3), dec!(20)),
+            (from + Duration::hours(5), dec!(15)),
+        ];
+        let buckets = chart::buckets(&series, from, from + Duration::hours(8), 8);
+        assert_eq!(
+            buckets,
+            vec![
+                None,
+                Some(dec!(10)),
+                Some(dec!(10)),
+                Some(dec!(20)),
+                Some(dec!(20)),
+                Some(dec!(15)),
+                Some(dec!(15)),
+                Some(dec!(15)),
+            ]
+        );
+        assert_eq!(chart::sparkline(&buckets), " ▁▁██▅▅▅");
+        assert_eq!(chart::sparkline(&[Some(dec!(1)), Some(dec!(1))]), "▅▅");
+        assert_eq!(chart::sparkline(&[None, None]), "");
+    }
+
+    #[test]
+    fn weekly_run_is_the_next_monday_0010() {
+        // monday before 00:10 runs the same day
+        assert_eq!(
+            next_weekly_run(at("2024-05-06T00:05:00Z")),
+            at("2024-05-06T00:10:00Z")
+        );
+        assert_eq!(
+            next_weekly_run(at("2024-05-06T00:10:00Z")),
+            at("2024-05-13T00:10:00Z")
+        );
+        assert_eq!(
+            next_weekly_run(at("2024-05-08T12:00:00Z")),
+            at("2024-05-13T00:10:00Z")
+        );
+        assert_eq!(
+            next_weekly_run(at("2024-05-12T23:59:59Z")),
+            at("2024-05-13T00:10:00Z")
+        );
+    }
+
+    #[tokio::test]
+    async fn weekly_charts_are_uploaded_with_a_bot_token() {
+        let server = MockServer::start().await;
+        server.respond(
+            "/files.getUploadURLExternal",
+            vec![ok(&format!(
+                r#"{{"ok": true, "upload_url": "{}/upload", "file_id": "F1"}}"#,
+                server.url
+            ))],
+        );
+        server.respond("/upload", vec![ok("OK")]);
+        server.respond(
+            "/files.completeUploadExternal",
+            vec![
+                ok(r#"{"ok": true}"#),
+                ok(r#"{"ok": false, "error": "not_in_channel"}"#),
+            ],
+        );
+        let cfg = TimelineConfig {
+            slack_bot_token: "xoxb-test".to_string(),
+            slack_channel_id: "C1".to_string(),
+            slack_api_url: server.url.clone(),
+            ..Default::default()
+        };
+        let uploader = Uploader::from_config(&cfg).unwrap();
+        let out = Buffer::default();
+        let router = Router::new(
+            &AlertRoutingConfig::default(),
+            Arc::new(TextSink::new(Box::new(out.clone()))),
+        );
+
+        let t = Timeline::in_memory().unwrap();
+        let from = at("2024-05-06T00:00:00Z");
+        for (h, usdt) in [(1, dec!(100)), (30, dec!(120))] {
+            t.append(
+                "prod",
+                from + Duration::hours(h),
+                &[("USDT".to_string(), usdt)].into(),
+            )
+            .unwrap();
+        }
+        let to = from + Duration::days(7);
+
+        send_weekly(&t, &cfg, Some(&uploader), &router, from, to)
+            .await
+            .unwrap();
+        assert!(server.requests("/upload")[0].contains("PNG"));
+        let complete = &server.requests("/files.completeUploadExternal")[0];
+        assert!(complete.contains(r#""channel_id":"C1""#), "{}", complete);
+        assert!(complete.contains(r#""id":"F1""#), "{}", complete);
+        assert!(out.0.lock().unwrap().is_empty());
+
+        // falls back to the sparklines when the upload fails
+        send_weekly(&t, &cfg, Some(&uploader), &router, from, to)
+            .await
+            .unwrap();
+        let sent = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert!(sent.contains("WEEKLY BALANCES"), "{}", sent);
+    }
+}
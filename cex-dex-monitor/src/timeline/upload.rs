@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use cex_dex_monitor::TimelineConfig;
+use reqwest::header::CONTENT_TYPE;
+use serde::Deserialize;
+use serde_json::json;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// Uploader posts files to a slack channel with a bot token, through
+// files.getUploadURLExternal and files.completeUploadExternal
+pub struct Uploader {
+    client: reqwest::Client,
+    api_url: String,
+    token: String,
+    channel_id: String,
+}
+
+// a png of a chart
+pub struct File {
+    pub title: String,
+    pub filename: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    #[serde(default)]
+    error: String,
+    upload_url: Option<String>,
+    file_id: Option<String>,
+}
+
+impl Uploader {
+    // None without a bot token or a channel
+    pub fn from_config(cfg: &TimelineConfig) -> Option<Uploader> {
+        if cfg.slack_bot_token.is_empty() || cfg.slack_channel_id.is_empty() {
+            return None;
+        }
+        Some(Uploader {
+            client: reqwest::Client::new(),
+            api_url: cfg.slack_api_url.trim_end_matches('/').to_string(),
+            token: cfg.slack_bot_token.clone(),
+            channel_id: cfg.slack_channel_id.clone(),
+        })
+    }
+
+    // one message with every file and comment as text
+    pub async fn upload(&self, comment: &str, files: &[File]) -> anyhow::Result<()> {
+        let mut uploaded = Vec::new();
+        for f in files {
+            let resp = self
+                .call(
+                    "files.getUploadURLExternal",
+                    self.client
+                        .post(self.method_url("files.getUploadURLExternal"))
+                        .form(&[
+                            ("filename", f.filename.clone()),
+                            ("length", f.bytes.len().to_string()),
+                        ]),
+                )
+                .await?;
+            let (Some(upload_url), Some(file_id)) = (resp.upload_url, resp.file_id) else {
+                return Err(anyhow::format_err!(
+                    "files.getUploadURLExternal: no upload url"
+                ));
+            };
+
+            let status = self
+                .client
+                .post(upload_url)
+                .body(f.bytes.clone())
+                .timeout(TIMEOUT)
+                .send()
+                .await
+                // the upload url is a credential
+                .map_err(|e| anyhow::format_err!("upload {}: {}", f.filename, e.without_url()))?
+                .status();
+            if !status.is_success() {
+                return Err(anyhow::format_err!(
+                    "upload {}: http status {}",
+                    f.filename,
+                    status
+                ));
+            }
+            uploaded.push(json!({"id": file_id, "title": f.title}));
+        }
+
+        self.call(
+            "files.completeUploadExternal",
+            self.client
+                .post(self.method_url("files.completeUploadExternal"))
+                .header(CONTENT_TYPE, "application/json; charset=utf-8")
+                .body(
+                    json!({
+                        "files": uploaded,
+                        "channel_id": self.channel_id,
+                        "initial_comment": comment,
+                    })
+                    .to_string(),
+                ),
+        )
+        .await?;
+        Ok(())
+    }
+
+    fn method_url(&self, method: &str) -> String {
+        format!("{}/{}", self.api_url, method)
+    }
+
+    async fn call(
+        &self,
+        method: &str,
+        req: reqwest::RequestBuilder,
+    ) -> anyhow::Result<ApiResponse> {
+        let body = req
+            .bearer_auth(&self.token)
+            .timeout(TIMEOUT)
+            .send()
+            .await?
+            .text()
+            .await?;
+        let resp: ApiResponse = serde_json::from_str(&body)
+            .map_err(|e| anyhow::format_err!("{}: decode response: {}", method, e))?;
+        if !resp.ok {
+            return Err(anyhow::format_err!("{}: {}", method, resp.error));
+        }
+        Ok(resp)
+    }
+}
@@ -1,7 +1,8 @@
 #[cfg(test)]
 mod tests {
-    use crate::{binanceclient::client::BinanceClient, *};
+    use crate::binanceclient::client::BinanceClient;
     use std::env;
+    use tokio::io::AsyncWriteExt;
 
     #[tokio::test]
     async fn test_get_open_orders() {
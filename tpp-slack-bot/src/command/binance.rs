@@ -0,0 +1,127 @@
+use std::{error::Error, sync::Arc};
+
+use async_trait::async_trait;
+
+use super::router::{Args, Command, Response};
+use crate::{
+    binanceclient::client::{AccountInfoResp, BinanceClient, BinanceOrder},
+    slackclient::client::SlackWSSlashCommandPayload,
+    tokiolog,
+};
+
+pub struct OpenOrders {
+    b_client: Arc<BinanceClient>,
+}
+
+impl OpenOrders {
+    pub fn new(b_client: Arc<BinanceClient>) -> OpenOrders {
+        OpenOrders { b_client }
+    }
+}
+
+#[async_trait]
+impl Command for OpenOrders {
+    fn name(&self) -> &str {
+        "/openorders"
+    }
+
+    fn help(&self) -> &str {
+        "open orders of the binance account"
+    }
+
+    async fn handle(
+        &self,
+        _: Args,
+        _: &SlackWSSlashCommandPayload,
+    ) -> Result<Response, Box<dyn Error + Send + Sync>> {
+        let binance_orders = self
+            .b_client
+            .get_open_order_service()
+            .exec()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(Response::ephemeral(stringtify_binance_orders(
+            &binance_orders,
+        )))
+    }
+}
+
+pub struct CexBalances {
+    b_client: Arc<BinanceClient>,
+}
+
+impl CexBalances {
+    pub fn new(b_client: Arc<BinanceClient>) -> CexBalances {
+        CexBalances { b_client }
+    }
+}
+
+#[async_trait]
+impl Command for CexBalances {
+    fn name(&self) -> &str {
+        "/cexbalances"
+    }
+
+    fn help(&self) -> &str {
+        "non zero free balances of the binance account"
+    }
+
+    async fn handle(
+        &self,
+        _: Args,
+        _: &SlackWSSlashCommandPayload,
+    ) -> Result<Response, Box<dyn Error + Send + Sync>> {
+        let account_info = self
+            .b_client
+            .get_account_info_service()
+            .exec()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let (resp, invalid) = stringtify_cex_balances(&account_info);
+        for (asset, free) in invalid {
+            tokiolog::logger::log_info(format!("INVALID FREE BALANCE {} {:?}\n", asset, free))
+                .await;
+        }
+
+        Ok(Response::ephemeral(resp))
+    }
+}
+
+fn stringtify_binance_orders(v: &[BinanceOrder]) -> String {
+    if v.is_empty() {
+        return String::from("no order found");
+    }
+
+    let mut str_resp = String::from("");
+    for ord in v {
+        str_resp.push_str(&format!("{}\n", ord));
+    }
+
+    str_resp
+}
+
+// the non zero free balances, and the (asset, free) pairs with a free balance
+// that is not a number
+fn stringtify_cex_balances(acc_info: &AccountInfoResp) -> (String, Vec<(String, String)>) {
+    let mut resp = String::new();
+    let mut invalid = Vec::new();
+
+    for b in &acc_info.balances {
+        let free = b.free.parse::<f64>();
+        if free.is_err() {
+            invalid.push((b.asset.clone(), b.free.clone()));
+        }
+        if free.unwrap_or(0.0) == 0.0 {
+            continue;
+        }
+
+        resp.push_str(&format!(
+            "{}: free {}  || locked: {}\n",
+            b.asset, b.free, b.locked
+        ));
+    }
+
+    (resp, invalid)
+}
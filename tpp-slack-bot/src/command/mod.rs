@@ -0,0 +1,3 @@
+pub mod binance;
+pub mod router;
+mod test;
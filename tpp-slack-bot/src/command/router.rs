@@ -0,0 +1,208 @@
+use std::{collections::BTreeMap, error::Error};
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::{slackclient::client::SlackWSSlashCommandPayload, tokiolog};
+
+pub const HELP_COMMAND: &str = "/help";
+
+// Command is a slash command the bot answers
+#[async_trait]
+pub trait Command: Send + Sync {
+    // with the leading slash, e.g. /openorders
+    fn name(&self) -> &str;
+
+    fn help(&self) -> &str;
+
+    // shown after the name in /help and on a parse error, e.g. <asset> [--all]
+    fn usage(&self) -> &str {
+        ""
+    }
+
+    // checks the text typed after the command
+    fn parse(&self, text: &str) -> Result<Args, String> {
+        let args = Args::parse(text);
+        if !args.positional.is_empty() || !args.flags.is_empty() {
+            return Err(String::from("no argument expected"));
+        }
+        Ok(args)
+    }
+
+    async fn handle(
+        &self,
+        args: Args,
+        payload: &SlackWSSlashCommandPayload,
+    ) -> Result<Response, Box<dyn Error + Send + Sync>>;
+}
+
+// Args is the text after a command split on whitespaces, --key value and
+// --key=value are flags, a --key followed by another flag or nothing is "true"
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    pub positional: Vec<String>,
+    pub flags: BTreeMap<String, String>,
+}
+
+#[allow(dead_code)]
+impl Args {
+    pub fn parse(text: &str) -> Args {
+        let mut args = Args::default();
+        let mut words = text.split_whitespace().peekable();
+        while let Some(w) = words.next() {
+            let Some(key) = w.strip_prefix("--") else {
+                args.positional.push(String::from(w));
+                continue;
+            };
+            if let Some((key, value)) = key.split_once('=') {
+                args.flags.insert(String::from(key), String::from(value));
+                continue;
+            }
+            let value = match words.peek() {
+                Some(v) if !v.starts_with("--") => words.next().unwrap(),
+                _ => "true",
+            };
+            args.flags.insert(String::from(key), String::from(value));
+        }
+        args
+    }
+
+    pub fn flag(&self, key: &str) -> Option<&str> {
+        self.flags.get(key).map(|v| v.as_str())
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Response {
+    pub text: String,
+    // ephemeral: only the user who typed the command sees it
+    pub response_type: ResponseType,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[allow(dead_code)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseType {
+    Ephemeral,
+    InChannel,
+}
+
+#[allow(dead_code)]
+impl Response {
+    pub fn ephemeral(text: String) -> Response {
+        Response {
+            text,
+            response_type: ResponseType::Ephemeral,
+        }
+    }
+
+    pub fn in_channel(text: String) -> Response {
+        Response {
+            text,
+            response_type: ResponseType::InChannel,
+        }
+    }
+}
+
+// Router dispatches the slash commands to the registered commands and posts
+// their response, or what went wrong, to the response url of the command
+pub struct Router {
+    commands: BTreeMap<String, Box<dyn Command>>,
+    http_client: reqwest::Client,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router {
+            commands: BTreeMap::new(),
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn register(&mut self, command: Box<dyn Command>) {
+        let name = String::from(command.name());
+        if name == HELP_COMMAND || self.commands.contains_key(&name) {
+            panic!("command {} registered twice", name);
+        }
+        self.commands.insert(name, command);
+    }
+
+    pub async fn dispatch(&self, payload: &SlackWSSlashCommandPayload) {
+        let resp = self.respond(payload).await;
+        if let Err(e) = self.post_response(&payload.response_url, &resp).await {
+            tokiolog::logger::log_info(format!(
+                "POST RESPONSE OF {} FAILED {}\n",
+                payload.command, e
+            ))
+            .await;
+        }
+    }
+
+    // the response to the command, errors included
+    pub async fn respond(&self, payload: &SlackWSSlashCommandPayload) -> Response {
+        if payload.command == HELP_COMMAND {
+            return Response::ephemeral(self.help());
+        }
+
+        let command = match self.commands.get(&payload.command) {
+            Some(c) => c,
+            None => {
+                return Response::ephemeral(format!(
+                    "unknown command {}, try {}",
+                    payload.command, HELP_COMMAND
+                ))
+            }
+        };
+
+        let args = match command.parse(&payload.text) {
+            Ok(args) => args,
+            Err(e) => {
+                return Response::ephemeral(format!("{}\nusage: {}", e, usage_line(&**command)))
+            }
+        };
+
+        match command.handle(args, payload).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                tokiolog::logger::log_info(format!("COMMAND {} FAILED {}\n", payload.command, e))
+                    .await;
+                Response::ephemeral(format!("{} failed: {}", payload.command, e))
+            }
+        }
+    }
+
+    pub fn help(&self) -> String {
+        let mut help = format!("{}: list the commands\n", HELP_COMMAND);
+        for command in self.commands.values() {
+            help.push_str(&format!("{}: {}\n", usage_line(&**command), command.help()));
+        }
+        help
+    }
+
+    async fn post_response(
+        &self,
+        response_url: &str,
+        resp: &Response,
+    ) -> Result<(), Box<dyn Error>> {
+        let post_response = self
+            .http_client
+            .post(url::Url::parse(response_url)?)
+            .header("Content-type", "application/json; charset=utf-8")
+            .body(serde_json::to_string(resp)?)
+            .send()
+            .await?
+            .text()
+            .await?;
+
+        tokiolog::logger::log_info(format!("POST RESPONSE {}\n", post_response)).await;
+
+        Ok(())
+    }
+}
+
+fn usage_line(command: &dyn Command) -> String {
+    if command.usage().is_empty() {
+        return String::from(command.name());
+    }
+    format!("{} {}", command.name(), command.usage())
+}
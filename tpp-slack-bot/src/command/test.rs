@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use async_trait::async_trait;
+
+    use crate::{
+        command::router::{Args, Command, Response, Router},
+        slackclient::client::SlackWSSlashCommandPayload,
+    };
+
+    struct Echo;
+
+    #[async_trait]
+    impl Command for Echo {
+        fn name(&self) -> &str {
+            "/echo"
+        }
+
+        fn help(&self) -> &str {
+            "repeat the text"
+        }
+
+        fn usage(&self) -> &str {
+            "<text>... [--fail]"
+        }
+
+        fn parse(&self, text: &str) -> Result<Args, String> {
+            let args = Args::parse(text);
+            if args.positional.is_empty() {
+                return Err(String::from("no text"));
+            }
+            Ok(args)
+        }
+
+        async fn handle(
+            &self,
+            args: Args,
+            payload: &SlackWSSlashCommandPayload,
+        ) -> Result<Response, Box<dyn Error + Send + Sync>> {
+            if args.flag("fail").is_some() {
+                return Err("echo is broken".into());
+            }
+            Ok(Response::in_channel(format!(
+                "{}: {}",
+                payload.user_name,
+                args.positional.join(" ")
+            )))
+        }
+    }
+
+    fn payload(command: &str, text: &str) -> SlackWSSlashCommandPayload {
+        SlackWSSlashCommandPayload {
+            command: String::from(command),
+            text: String::from(text),
+            user_name: String::from("thanhpp"),
+            ..Default::default()
+        }
+    }
+
+    fn router() -> Router {
+        let mut router = Router::new();
+        router.register(Box::new(Echo));
+        router
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = Args::parse(" KNC  --since 2024-05-01 --json --size=10 USDT");
+        assert_eq!(args.positional, vec!["KNC", "USDT"]);
+        assert_eq!(args.flag("since"), Some("2024-05-01"));
+        assert_eq!(args.flag("json"), Some("true"));
+        assert_eq!(args.flag("size"), Some("10"));
+        assert_eq!(Args::parse(""), Args::default());
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let router = router();
+
+        assert_eq!(
+            router.respond(&payload("/echo", "hello  world")).await,
+            Response::in_channel(String::from("thanhpp: hello world"))
+        );
+        assert_eq!(
+            router.respond(&payload("/echo", "")).await,
+            Response::ephemeral(String::from("no text\nusage: /echo <text>... [--fail]"))
+        );
+        assert_eq!(
+            router.respond(&payload("/echo", "hello --fail")).await,
+            Response::ephemeral(String::from("/echo failed: echo is broken"))
+        );
+        assert_eq!(
+            router.respond(&payload("/unknown", "")).await,
+            Response::ephemeral(String::from("unknown command /unknown, try /help"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_help() {
+        let router = router();
+
+        assert_eq!(
+            router.respond(&payload("/help", "")).await,
+            Response::ephemeral(String::from(
+                "/help: list the commands\n/echo <text>... [--fail]: repeat the text\n"
+            ))
+        );
+    }
+}
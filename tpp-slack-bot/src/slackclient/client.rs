@@ -1,22 +1,14 @@
-use std::{error::Error, sync::Arc};
+use std::error::Error;
 
-use futures_util::{SinkExt, StreamExt}; // split websocket stream
 use serde::{Deserialize, Serialize};
-use tokio::{
-    io::AsyncWriteExt,
-    net::TcpStream,
-    sync::{
-        mpsc::{Receiver, Sender},
-        Mutex,
-    },
-    time,
+use tokio::sync::{
+    mpsc::{Receiver, Sender},
+    watch,
 };
-use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
 
-use crate::tokiolog;
+use super::socket::{AppsConnectionsOpen, ConnectionState, SocketModeClient, SocketModeConfig};
 
 #[allow(dead_code)]
-
 pub struct SlackClient {
     ws_token: String,
     api_token: String,
@@ -27,17 +19,14 @@ pub struct SlackClient {
 impl SlackClient {
     pub fn new(ws_token: String, api_token: String) -> SlackClient {
         SlackClient {
-            ws_token: ws_token,
-            api_token: api_token,
+            ws_token,
+            api_token,
             http_client: reqwest::Client::new(),
         }
     }
 
     pub async fn send_message(&self, channel: String, text: String) -> Result<(), Box<dyn Error>> {
-        let req = SlackSendMessageReq {
-            channel: channel,
-            text: text,
-        };
+        let req = SlackSendMessageReq { channel, text };
 
         let req_serialized = serde_json::to_string(&req)?;
 
@@ -57,151 +46,21 @@ impl SlackClient {
         Ok(())
     }
 
-    pub async fn get_ws_channel(&mut self) -> Receiver<String> {
+    // the envelopes of the socket mode connection, and its state
+    pub fn get_ws_channel(&self) -> (Receiver<String>, watch::Receiver<ConnectionState>) {
         let (tx, rx): (Sender<String>, Receiver<String>) = tokio::sync::mpsc::channel(100);
-        let client = self.http_client.clone();
-        let ws_token = self.ws_token.clone();
-        tokio::spawn(async move {
-            loop {
-                let ws = connect_ws(&client, &ws_token).await;
-                tokio::io::stdout().write(b"connected ws \n").await.unwrap();
-                let (mut write, mut read) = ws.split();
-                let arc_write = Arc::new(Mutex::new(&mut write));
-
-                // read.for_each(|item| async {
-                //     let data = match item {
-                //         Ok(it) => it.into_data(),
-                //         Err(e) => {
-                //             tokio::io::stdout()
-                //                 .write(format!("read data error: {}\n", e).as_bytes())
-                //                 .await
-                //                 .unwrap();
-                //             return;
-                //         }
-                //     };
-                //     let str_data = String::from_utf8(data).unwrap();
-                //     tokiolog::logger::log_info(format!("WS RECEIVE DATA {}\n", &str_data)).await;
-
-                //     // ack
-                //     if let Some(envelope_id) = get_envelope_id(&str_data).await {
-                //         let msg = SlackWSWithEnvelopeID {
-                //             envelope_id: envelope_id,
-                //         };
-                //         {
-                //             let mutex_write = Arc::clone(&arc_write);
-                //             let mut write_stream = mutex_write.lock().await;
-                //             (*write_stream)
-                //                 .send(Message::text(serde_json::to_string(&msg).unwrap()))
-                //                 .await
-                //                 .unwrap();
-                //         } // early unlock
-                //         tokiolog::logger::log_info(format!(
-                //             "ACK envelope id {}\n",
-                //             msg.envelope_id
-                //         ))
-                //         .await;
-                //     }
-
-                //     tx.clone().send(str_data).await.unwrap();
-                // })
-                // .await;
-
-                loop {
-                    let sleep = time::sleep(time::Duration::from_secs(60));
-                    tokio::pin!(sleep);
-                    let item = tokio::select! {
-                        v = read.next() => {
-                            match v {
-                                Some(x) => match x {
-                                    Ok(ok) => ok.into_data(),
-                                    Err(_) => {
-                                        break;
-                                    }
-                                },
-                                None => {
-                                    break;
-                                }
-                            }
-                        }
-                        _ = &mut sleep => {
-                            break;
-                        }
-                    };
-
-                    let str_data = String::from_utf8(item).unwrap();
-
-                    tokiolog::logger::log_info(format!("WS RECEIVE DATA {}\n", &str_data)).await;
-                    // ack
-                    if let Some(envelope_id) = get_envelope_id(&str_data).await {
-                        let msg = SlackWSWithEnvelopeID {
-                            envelope_id: envelope_id,
-                        };
-                        let mutex_write = Arc::clone(&arc_write);
-                        let mut write_stream = mutex_write.lock().await;
-                        (*write_stream)
-                            .send(Message::text(serde_json::to_string(&msg).unwrap()))
-                            .await
-                            .unwrap();
-                        tokiolog::logger::log_info(format!(
-                            "ACK envelope id {}\n",
-                            msg.envelope_id
-                        ))
-                        .await;
-
-                        tx.clone().send(str_data).await.unwrap();
-                    };
-                }
-                tokio::io::stdout()
-                    .write(b"ws loop ended \n")
-                    .await
-                    .unwrap();
-            }
-        });
-
-        rx
-    }
-}
-
-async fn connect_ws(
-    http_client: &reqwest::Client,
-    ws_token: &str,
-) -> WebSocketStream<MaybeTlsStream<TcpStream>> {
-    let open_conn_resp_txt = http_client
-        .post("https://slack.com/api/apps.connections.open")
-        .header("Content-type", "application/x-www-form-urlencoded")
-        .header("Authorization", format!("Bearer {}", ws_token))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-    let slack_open_conn_resp: SlackOpenConnResp = serde_json::from_str(&open_conn_resp_txt)
-        .expect(format!("received data {}", open_conn_resp_txt).as_str());
-    if !slack_open_conn_resp.ok {
-        panic!("get slack open conn error {}", open_conn_resp_txt);
-    }
-    tokio::io::stdout()
-        .write(b"GET WS URL OK \n")
-        .await
-        .unwrap();
-
-    let ws_url = url::Url::parse(&slack_open_conn_resp.url.unwrap()).unwrap();
-    let (ws_stream, _) = tokio_tungstenite::connect_async(ws_url).await.unwrap();
-    tokio::io::stdout()
-        .write(b"CONNECT WS OK \n")
-        .await
-        .unwrap();
-
-    ws_stream
-}
-
-async fn get_envelope_id(data: &str) -> Option<String> {
-    if let Ok(msg) = serde_json::from_str::<SlackWSWithEnvelopeID>(data) {
-        return Some(msg.envelope_id);
+        let client = SocketModeClient::new(
+            Box::new(AppsConnectionsOpen::new(
+                self.http_client.clone(),
+                self.ws_token.clone(),
+            )),
+            SocketModeConfig::default(),
+        );
+        let state = client.state();
+        tokio::spawn(client.run(tx));
+
+        (rx, state)
     }
-
-    None
 }
 
 #[derive(Serialize)]
@@ -221,6 +80,29 @@ pub struct SlackWSWithEnvelopeID {
     pub envelope_id: String,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct SlackWSSlashCommandMsg {
+    pub payload: SlackWSSlashCommandPayload,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[allow(dead_code)]
+pub struct SlackWSSlashCommandPayload {
+    pub token: String,
+    pub team_id: String,
+    pub team_domain: String,
+    pub channel_id: String,
+    pub channel_name: String,
+    pub user_id: String,
+    pub user_name: String,
+    pub command: String,
+    pub text: String,
+    pub api_app_id: String,
+    pub is_enterprise_install: String,
+    pub response_url: String,
+    pub trigger_id: String,
+}
+
 #[cfg(test)]
 mod tests {
     use std::env;
@@ -242,9 +124,8 @@ mod tests {
     // TPP_SLACK_WS_TOKEN
     #[tokio::test]
     async fn test_ws() {
-        let mut client =
-            SlackClient::new(env::var("TPP_SLACK_WS_TOKEN").unwrap(), String::from(""));
-        let mut rx = client.get_ws_channel().await;
+        let client = SlackClient::new(env::var("TPP_SLACK_WS_TOKEN").unwrap(), String::from(""));
+        let (mut rx, _) = client.get_ws_channel();
 
         for _ in 0..10 {
             let _ = rx.recv().await.unwrap();
@@ -0,0 +1,471 @@
+use std::{error::Error, time::Duration};
+
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt}; // split websocket stream
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{mpsc::Sender, watch},
+    time::{self, Instant},
+};
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+
+use super::client::{SlackOpenConnResp, SlackWSWithEnvelopeID};
+use crate::tokiolog;
+
+// ConnectionOpener returns the url of a new socket mode websocket
+#[async_trait]
+pub trait ConnectionOpener: Send + Sync {
+    async fn open(&self) -> Result<String, Box<dyn Error + Send + Sync>>;
+}
+
+// AppsConnectionsOpen asks slack for the url with the app level token
+pub struct AppsConnectionsOpen {
+    http_client: reqwest::Client,
+    ws_token: String,
+}
+
+impl AppsConnectionsOpen {
+    pub fn new(http_client: reqwest::Client, ws_token: String) -> AppsConnectionsOpen {
+        AppsConnectionsOpen {
+            http_client,
+            ws_token,
+        }
+    }
+}
+
+#[async_trait]
+impl ConnectionOpener for AppsConnectionsOpen {
+    async fn open(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let open_conn_resp_txt = self
+            .http_client
+            .post("https://slack.com/api/apps.connections.open")
+            .header("Content-type", "application/x-www-form-urlencoded")
+            .bearer_auth(&self.ws_token)
+            .send()
+            .await?
+            .text()
+            .await?;
+        let resp = serde_json::from_str::<SlackOpenConnResp>(&open_conn_resp_txt)
+            .map_err(|e| format!("{}, received data {}", e, open_conn_resp_txt))?;
+        match resp.url {
+            Some(url) if resp.ok => Ok(url),
+            _ => Err(format!("get slack open conn error {}", open_conn_resp_txt).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    // attempt: failed connections in a row before this one
+    Connecting { attempt: u32 },
+    // the websocket is open, slack did not say hello yet
+    Opened,
+    Connected,
+    Disconnected { reason: String, retry_in: Duration },
+}
+
+pub struct SocketModeConfig {
+    // a ping is sent at this interval to keep the connection checked
+    pub ping_interval: Duration,
+    // the connection is dropped when nothing was received for this long
+    pub idle_timeout: Duration,
+    pub backoff_initial: Duration,
+    pub backoff_max: Duration,
+}
+
+impl Default for SocketModeConfig {
+    fn default() -> Self {
+        SocketModeConfig {
+            ping_interval: Duration::from_secs(20),
+            idle_timeout: Duration::from_secs(60),
+            backoff_initial: Duration::from_secs(1),
+            backoff_max: Duration::from_secs(60),
+        }
+    }
+}
+
+// Backoff doubles the wait after each failed connection, up to max
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Backoff {
+        Backoff {
+            initial,
+            max,
+            attempt: 0,
+        }
+    }
+
+    pub fn next(&mut self) -> Duration {
+        let wait = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        wait
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+}
+
+// why a connection ended
+enum SessionEnd {
+    // slack asked to reconnect, no wait
+    Refresh(String),
+    // the connection failed or died, reconnect after a backoff
+    Lost(String),
+    // the receiver of the envelopes is dropped, stop
+    Closed,
+}
+
+#[derive(Deserialize)]
+struct SlackWSEnvelope {
+    #[serde(alias = "type")]
+    msg_type: String,
+    envelope_id: Option<String>,
+    // of a disconnect: warning, refresh_requested, link_disabled
+    reason: Option<String>,
+}
+
+// SocketModeClient keeps a socket mode connection open, acks the envelopes and
+// forwards them, and reconnects when slack asks to or the connection dies
+pub struct SocketModeClient {
+    opener: Box<dyn ConnectionOpener>,
+    cfg: SocketModeConfig,
+    backoff: Backoff,
+    state: watch::Sender<ConnectionState>,
+}
+
+impl SocketModeClient {
+    pub fn new(opener: Box<dyn ConnectionOpener>, cfg: SocketModeConfig) -> SocketModeClient {
+        let (state, _) = watch::channel(ConnectionState::Connecting { attempt: 0 });
+        SocketModeClient {
+            opener,
+            backoff: Backoff::new(cfg.backoff_initial, cfg.backoff_max),
+            cfg,
+            state,
+        }
+    }
+
+    pub fn state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    // until tx is closed
+    pub async fn run(mut self, tx: Sender<String>) {
+        loop {
+            self.state.send_replace(ConnectionState::Connecting {
+                attempt: self.backoff.attempt(),
+            });
+
+            let end = match self.opener.open().await {
+                Err(e) => SessionEnd::Lost(format!("open connection: {}", e)),
+                Ok(url) => match tokio_tungstenite::connect_async(url.as_str()).await {
+                    Err(e) => SessionEnd::Lost(format!("connect websocket: {}", e)),
+                    Ok((ws, _)) => self.session(ws, &tx).await,
+                },
+            };
+
+            let (reason, retry_in) = match end {
+                SessionEnd::Closed => return,
+                SessionEnd::Refresh(reason) => (reason, Duration::ZERO),
+                SessionEnd::Lost(reason) => (reason, self.backoff.next()),
+            };
+            tokiolog::logger::log_info(format!(
+                "WS DISCONNECTED {}, retry in {:?}\n",
+                reason, retry_in
+            ))
+            .await;
+            self.state
+                .send_replace(ConnectionState::Disconnected { reason, retry_in });
+            tokio::select! {
+                _ = time::sleep(retry_in) => {}
+                _ = tx.closed() => return,
+            }
+        }
+    }
+
+    async fn session<S>(&mut self, ws: WebSocketStream<S>, tx: &Sender<String>) -> SessionEnd
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.state.send_replace(ConnectionState::Opened);
+        let (mut write, mut read) = ws.split();
+        let mut ping = time::interval_at(
+            Instant::now() + self.cfg.ping_interval,
+            self.cfg.ping_interval,
+        );
+        let mut last_received = Instant::now();
+
+        loop {
+            let item = tokio::select! {
+                v = read.next() => v,
+                _ = ping.tick() => {
+                    if let Err(e) = write.send(Message::Ping(Default::default())).await {
+                        return SessionEnd::Lost(format!("send ping: {}", e));
+                    }
+                    continue;
+                }
+                _ = time::sleep_until(last_received + self.cfg.idle_timeout) => {
+                    return SessionEnd::Lost(format!(
+                        "nothing received for {:?}",
+                        self.cfg.idle_timeout
+                    ));
+                }
+                _ = tx.closed() => return SessionEnd::Closed,
+            };
+            last_received = Instant::now();
+
+            // pings are answered by tungstenite
+            let str_data = match item {
+                None => return SessionEnd::Lost(String::from("websocket ended")),
+                Some(Err(e)) => return SessionEnd::Lost(format!("read websocket: {}", e)),
+                Some(Ok(Message::Close(frame))) => {
+                    return SessionEnd::Lost(format!("websocket closed {:?}", frame))
+                }
+                Some(Ok(Message::Text(text))) => text.to_string(),
+                Some(Ok(_)) => continue,
+            };
+            tokiolog::logger::log_info(format!("WS RECEIVE DATA {}\n", &str_data)).await;
+
+            let envelope = match serde_json::from_str::<SlackWSEnvelope>(&str_data) {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    tokiolog::logger::log_info(format!("INVALID WS DATA {}\n", e)).await;
+                    continue;
+                }
+            };
+            match envelope.msg_type.as_str() {
+                "hello" => {
+                    self.backoff.reset();
+                    self.state.send_replace(ConnectionState::Connected);
+                    continue;
+                }
+                "disconnect" => {
+                    let reason = envelope.reason.unwrap_or_default();
+                    let end = format!("disconnect: {}", reason);
+                    // warning: slack closes the connection in a few seconds
+                    return match reason.as_str() {
+                        "refresh_requested" | "warning" => SessionEnd::Refresh(end),
+                        _ => SessionEnd::Lost(end),
+                    };
+                }
+                _ => {}
+            }
+
+            // ack
+            let Some(envelope_id) = envelope.envelope_id else {
+                continue;
+            };
+            let ack = serde_json::to_string(&SlackWSWithEnvelopeID {
+                envelope_id: envelope_id.clone(),
+            })
+            .unwrap();
+            if let Err(e) = write.send(Message::text(ack)).await {
+                return SessionEnd::Lost(format!("ack envelope {}: {}", envelope_id, e));
+            }
+            tokiolog::logger::log_info(format!("ACK envelope id {}\n", envelope_id)).await;
+
+            if tx.send(str_data).await.is_err() {
+                return SessionEnd::Closed;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        error::Error,
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::{
+        net::{TcpListener, TcpStream},
+        sync::{mpsc, watch},
+        time,
+    };
+    use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+
+    use super::*;
+
+    // StandIn opens connections to a local websocket server, failing the first
+    // ones
+    struct StandIn {
+        url: String,
+        failures: AtomicU32,
+        opened: Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl ConnectionOpener for StandIn {
+        async fn open(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+            self.opened.fetch_add(1, Ordering::SeqCst);
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                return Err("stand in is down".into());
+            }
+            Ok(self.url.clone())
+        }
+    }
+
+    fn cfg() -> SocketModeConfig {
+        SocketModeConfig {
+            ping_interval: Duration::from_millis(50),
+            idle_timeout: Duration::from_millis(300),
+            backoff_initial: Duration::from_millis(10),
+            backoff_max: Duration::from_millis(40),
+        }
+    }
+
+    // the server and a client connected to it
+    async fn start(
+        failures: u32,
+    ) -> (
+        TcpListener,
+        Arc<AtomicU32>,
+        mpsc::Receiver<String>,
+        watch::Receiver<ConnectionState>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let opened = Arc::new(AtomicU32::new(0));
+        let client = SocketModeClient::new(
+            Box::new(StandIn {
+                url: format!("ws://{}", listener.local_addr().unwrap()),
+                failures: AtomicU32::new(failures),
+                opened: Arc::clone(&opened),
+            }),
+            cfg(),
+        );
+        let state = client.state();
+        let (tx, rx) = mpsc::channel(10);
+        tokio::spawn(client.run(tx));
+        (listener, opened, rx, state)
+    }
+
+    async fn accept(listener: &TcpListener) -> WebSocketStream<TcpStream> {
+        let (stream, _) = listener.accept().await.unwrap();
+        tokio_tungstenite::accept_async(stream).await.unwrap()
+    }
+
+    async fn wait_state(
+        state: &mut watch::Receiver<ConnectionState>,
+        f: impl Fn(&ConnectionState) -> bool,
+    ) -> ConnectionState {
+        time::timeout(Duration::from_secs(5), state.wait_for(|s| f(s)))
+            .await
+            .unwrap()
+            .unwrap()
+            .clone()
+    }
+
+    async fn next_text(ws: &mut WebSocketStream<TcpStream>) -> String {
+        loop {
+            match ws.next().await.unwrap().unwrap() {
+                Message::Text(text) => return text.to_string(),
+                _ => continue,
+            }
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut b = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        let waits = (0..5).map(|_| b.next().as_secs()).collect::<Vec<u64>>();
+        assert_eq!(waits, vec![1, 2, 4, 5, 5]);
+        b.reset();
+        assert_eq!(b.next(), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_ack_forward_and_refresh() {
+        let (listener, opened, mut rx, mut state) = start(0).await;
+
+        let mut ws = accept(&listener).await;
+        ws.send(Message::text(r#"{"type":"hello"}"#)).await.unwrap();
+        wait_state(&mut state, |s| *s == ConnectionState::Connected).await;
+
+        let event = r#"{"type":"slash_commands","envelope_id":"e1","payload":{}}"#;
+        ws.send(Message::text(event)).await.unwrap();
+        assert_eq!(next_text(&mut ws).await, r#"{"envelope_id":"e1"}"#);
+        assert_eq!(rx.recv().await.unwrap(), event);
+
+        // reconnects at once, without counting a failure
+        ws.send(Message::text(
+            r#"{"type":"disconnect","reason":"refresh_requested"}"#,
+        ))
+        .await
+        .unwrap();
+        assert_eq!(
+            wait_state(&mut state, |s| matches!(
+                s,
+                ConnectionState::Connecting { .. }
+            ))
+            .await,
+            ConnectionState::Connecting { attempt: 0 }
+        );
+
+        let mut ws = accept(&listener).await;
+        ws.send(Message::text(r#"{"type":"hello"}"#)).await.unwrap();
+        wait_state(&mut state, |s| *s == ConnectionState::Connected).await;
+        assert_eq!(opened.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_backoff_until_connected() {
+        let (listener, opened, _rx, mut state) = start(3).await;
+
+        let mut ws = accept(&listener).await;
+        wait_state(&mut state, |s| *s == ConnectionState::Opened).await;
+        assert_eq!(opened.load(Ordering::SeqCst), 4);
+
+        ws.send(Message::text(r#"{"type":"hello"}"#)).await.unwrap();
+        wait_state(&mut state, |s| *s == ConnectionState::Connected).await;
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_when_silent() {
+        let (listener, opened, _rx, mut state) = start(0).await;
+
+        // never read, so the pings are not answered
+        let mut silent = accept(&listener).await;
+        silent
+            .send(Message::text(r#"{"type":"hello"}"#))
+            .await
+            .unwrap();
+        wait_state(&mut state, |s| *s == ConnectionState::Connected).await;
+
+        let disconnected = wait_state(&mut state, |s| {
+            matches!(s, ConnectionState::Disconnected { .. })
+        })
+        .await;
+        assert_eq!(
+            disconnected,
+            ConnectionState::Disconnected {
+                reason: String::from("nothing received for 300ms"),
+                retry_in: Duration::from_millis(10)
+            }
+        );
+
+        let _ws = accept(&listener).await;
+        assert_eq!(opened.load(Ordering::SeqCst), 2);
+        drop(silent);
+    }
+}